@@ -0,0 +1,186 @@
+use crate::secret::Secret;
+use async_trait::async_trait;
+use ethers::providers::{
+    Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, RpcError, Ws, WsClientError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+/// JSON-RPC error codes that mean "slow down" rather than a real answer
+const RATE_LIMIT_CODES: &[i64] = &[429, -32005];
+
+/// Whether an RPC URL is a WebSocket endpoint
+pub fn is_websocket(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// A single HTTP or WebSocket endpoint
+#[derive(Debug, Clone)]
+enum Transport {
+    Http(Http),
+    Ws(Ws),
+}
+
+/// Error from either kind of endpoint
+#[derive(Debug)]
+pub enum TransportError {
+    Http(HttpClientError),
+    Ws(WsClientError),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Http(e) => e.fmt(f),
+            TransportError::Ws(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransportError::Http(e) => e.source(),
+            TransportError::Ws(e) => e.source(),
+        }
+    }
+}
+
+impl From<HttpClientError> for TransportError {
+    fn from(e: HttpClientError) -> Self {
+        TransportError::Http(e)
+    }
+}
+
+impl From<WsClientError> for TransportError {
+    fn from(e: WsClientError) -> Self {
+        TransportError::Ws(e)
+    }
+}
+
+impl RpcError for TransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            TransportError::Http(e) => e.as_error_response(),
+            TransportError::Ws(e) => e.as_error_response(),
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            TransportError::Http(e) => e.as_serde_error(),
+            TransportError::Ws(e) => e.as_serde_error(),
+        }
+    }
+}
+
+impl From<TransportError> for ProviderError {
+    fn from(e: TransportError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+/// Transport over several RPC endpoints (HTTP or WebSocket). Requests go to the
+/// active endpoint; when it fails (connection error, unparseable response or
+/// rate limiting) the request is retried on the next endpoint, which becomes
+/// active. Ordinary JSON-RPC errors such as reverts are returned as-is.
+#[derive(Debug, Clone)]
+pub struct FallbackRpc {
+    endpoints: Arc<Vec<(Transport, Secret)>>,
+    active: Arc<AtomicUsize>,
+}
+
+impl FallbackRpc {
+    /// Connect to `urls`; WebSocket endpoints are connected up front
+    pub async fn connect(urls: &[Secret]) -> anyhow::Result<Self> {
+        if urls.is_empty() {
+            anyhow::bail!("No RPC URL configured");
+        }
+
+        let mut endpoints = Vec::with_capacity(urls.len());
+        for url in urls {
+            let transport = if is_websocket(url.expose()) {
+                let ws = Ws::connect(url.expose())
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to connect to RPC WebSocket {}: {}", url, e))?;
+                Transport::Ws(ws)
+            } else {
+                let http = Http::from_str(url.expose())
+                    .map_err(|e| anyhow::anyhow!("Invalid RPC URL {}: {}", url, e))?;
+                Transport::Http(http)
+            };
+            endpoints.push((transport, url.clone()));
+        }
+
+        Ok(Self { endpoints: Arc::new(endpoints), active: Arc::new(AtomicUsize::new(0)) })
+    }
+
+    /// URL of the endpoint requests currently go to
+    pub fn active_url(&self) -> &Secret {
+        &self.endpoints[self.active.load(Ordering::Relaxed)].1
+    }
+
+    pub fn endpoint_count(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    fn should_fail_over(error: &TransportError) -> bool {
+        match error {
+            TransportError::Http(HttpClientError::JsonRpcError(e))
+            | TransportError::Ws(WsClientError::JsonRpcError(e)) => {
+                RATE_LIMIT_CODES.contains(&e.code) || e.message.to_lowercase().contains("rate limit")
+            }
+            TransportError::Http(_) | TransportError::Ws(_) => true,
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FallbackRpc {
+    type Error = TransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // Serialized once so the same params can be resent to another endpoint
+        let params = serde_json::to_value(&params)
+            .map_err(|err| HttpClientError::SerdeJson { err, text: format!("{:?}", params) })?;
+
+        let start = self.active.load(Ordering::Relaxed);
+        let count = self.endpoints.len();
+        let mut offset = 0;
+
+        loop {
+            let index = (start + offset) % count;
+            let (ref transport, ref url) = self.endpoints[index];
+
+            let result = match transport {
+                Transport::Http(http) => http.request(method, params.clone()).await.map_err(TransportError::from),
+                Transport::Ws(ws) => ws.request(method, params.clone()).await.map_err(TransportError::from),
+            };
+            match result {
+                Ok(response) => return Ok(response),
+                Err(e) if offset + 1 < count && Self::should_fail_over(&e) => {
+                    let next = (index + 1) % count;
+                    // Only the first request to notice a failed endpoint switches
+                    if self.active.compare_exchange(index, next, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                        warn!(
+                            "RPC endpoint {} failed ({}), dropping it; now using {}",
+                            url,
+                            e.to_string().replace(url.expose(), &url.to_string()),
+                            self.endpoints[next].1
+                        );
+                    }
+                    offset += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
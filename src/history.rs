@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::error;
+
+/// One successful market reading. Token amounts are decimal strings since
+/// they don't fit in SQLite's 64-bit integers.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRow {
+    /// RFC 3339, UTC
+    pub timestamp: String,
+    pub market: String,
+    pub symbol: String,
+    pub available_liquidity: String,
+    pub total_borrows: String,
+    pub total_reserves: String,
+    pub utilization: Option<f64>,
+    pub supply_apy: Option<f64>,
+    pub borrow_apy: Option<f64>,
+}
+
+/// Database of market readings, one row per market per poll
+#[async_trait]
+pub trait History: Send + Sync {
+    /// Where the readings are kept, for logs; never includes a password
+    fn location(&self) -> String;
+
+    async fn append(&self, row: &HistoryRow) -> Result<()>;
+
+    /// The last `limit` readings, oldest first, optionally for one market
+    /// (address, case-insensitive)
+    async fn latest(&self, limit: usize, market: Option<&str>) -> Result<Vec<HistoryRow>>;
+}
+
+/// Open the database named by `database`: a `postgres://` or `postgresql://`
+/// URL, otherwise the path of a SQLite file (an optional `sqlite://` prefix is
+/// stripped)
+pub async fn open(database: &str) -> Result<Box<dyn History>> {
+    if database.starts_with("postgres://") || database.starts_with("postgresql://") {
+        return Ok(Box::new(PostgresHistory::connect(database).await?));
+    }
+    let path = database.strip_prefix("sqlite://").unwrap_or(database);
+    Ok(Box::new(SqliteHistory::open(path)?))
+}
+
+/// SQLite file of market readings
+pub struct SqliteHistory {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteHistory {
+    /// Open the database at `path`, creating it and the readings table if missing
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let connection = Connection::open(&path)
+            .with_context(|| format!("Failed to open history database {}", path.display()))?;
+
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS readings (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    market TEXT NOT NULL,
+                    symbol TEXT NOT NULL,
+                    available_liquidity TEXT NOT NULL,
+                    total_borrows TEXT NOT NULL,
+                    total_reserves TEXT NOT NULL,
+                    utilization REAL,
+                    supply_apy REAL,
+                    borrow_apy REAL
+                );
+                CREATE INDEX IF NOT EXISTS readings_market_timestamp ON readings (market, timestamp);",
+            )
+            .with_context(|| format!("Failed to create tables in history database {}", path.display()))?;
+
+        Ok(SqliteHistory { path, connection: Mutex::new(connection) })
+    }
+}
+
+#[async_trait]
+impl History for SqliteHistory {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn append(&self, row: &HistoryRow) -> Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO readings (timestamp, market, symbol, available_liquidity, total_borrows,
+                    total_reserves, utilization, supply_apy, borrow_apy)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    row.timestamp,
+                    row.market,
+                    row.symbol,
+                    row.available_liquidity,
+                    row.total_borrows,
+                    row.total_reserves,
+                    row.utilization,
+                    row.supply_apy,
+                    row.borrow_apy,
+                ],
+            )
+            .with_context(|| format!("Failed to write history database {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    async fn latest(&self, limit: usize, market: Option<&str>) -> Result<Vec<HistoryRow>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT timestamp, market, symbol, available_liquidity, total_borrows, total_reserves,
+                    utilization, supply_apy, borrow_apy
+                 FROM readings
+                 WHERE ?1 IS NULL OR lower(market) = lower(?1)
+                 ORDER BY id DESC
+                 LIMIT ?2",
+            )
+            .with_context(|| format!("Failed to query history database {}", self.path.display()))?;
+
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut rows = statement
+            .query_map(params![market, limit], |row| {
+                Ok(HistoryRow {
+                    timestamp: row.get(0)?,
+                    market: row.get(1)?,
+                    symbol: row.get(2)?,
+                    available_liquidity: row.get(3)?,
+                    total_borrows: row.get(4)?,
+                    total_reserves: row.get(5)?,
+                    utilization: row.get(6)?,
+                    supply_apy: row.get(7)?,
+                    borrow_apy: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .with_context(|| format!("Failed to read history database {}", self.path.display()))?;
+
+        rows.reverse();
+        Ok(rows)
+    }
+}
+
+/// Postgres table of market readings, for a database shared by several
+/// monitors or read by dashboards. Token amounts are NUMERIC and the timestamp
+/// a TIMESTAMPTZ so they can be charted without casts.
+pub struct PostgresHistory {
+    location: String,
+    client: tokio_postgres::Client,
+}
+
+impl PostgresHistory {
+    /// Connect to the database at `url`, creating the readings table if missing.
+    /// TLS is used when the server offers it (`sslmode=require` to insist).
+    pub async fn connect(url: &str) -> Result<Self> {
+        let config: tokio_postgres::Config = url.parse().context("Invalid Postgres URL in database_url")?;
+        let hosts: Vec<String> = config.get_hosts().iter().map(|host| match host {
+            tokio_postgres::config::Host::Tcp(host) => host.clone(),
+            tokio_postgres::config::Host::Unix(path) => path.display().to_string(),
+        }).collect();
+        let location = format!("postgres://{}/{}", hosts.join(","), config.get_dbname().unwrap_or_default());
+
+        let tls = postgres_native_tls::MakeTlsConnector::new(
+            native_tls::TlsConnector::new().context("Failed to set up TLS for Postgres")?,
+        );
+        let (client, connection) = config.connect(tls).await
+            .with_context(|| format!("Failed to connect to history database {}", location))?;
+        let connection_location = location.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("History database {} connection failed: {}", connection_location, e);
+            }
+        });
+
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS readings (
+                    id BIGSERIAL PRIMARY KEY,
+                    timestamp TIMESTAMPTZ NOT NULL,
+                    market TEXT NOT NULL,
+                    symbol TEXT NOT NULL,
+                    available_liquidity NUMERIC(78, 0) NOT NULL,
+                    total_borrows NUMERIC(78, 0) NOT NULL,
+                    total_reserves NUMERIC(78, 0) NOT NULL,
+                    utilization DOUBLE PRECISION,
+                    supply_apy DOUBLE PRECISION,
+                    borrow_apy DOUBLE PRECISION
+                );
+                CREATE INDEX IF NOT EXISTS readings_market_timestamp ON readings (market, timestamp);",
+            )
+            .await
+            .with_context(|| format!("Failed to create tables in history database {}", location))?;
+
+        Ok(PostgresHistory { location, client })
+    }
+}
+
+#[async_trait]
+impl History for PostgresHistory {
+    fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    async fn append(&self, row: &HistoryRow) -> Result<()> {
+        self.client
+            .execute(
+                "INSERT INTO readings (timestamp, market, symbol, available_liquidity, total_borrows,
+                    total_reserves, utilization, supply_apy, borrow_apy)
+                 VALUES ($1::text::timestamptz, $2, $3, $4::text::numeric, $5::text::numeric,
+                    $6::text::numeric, $7, $8, $9)",
+                &[
+                    &row.timestamp,
+                    &row.market,
+                    &row.symbol,
+                    &row.available_liquidity,
+                    &row.total_borrows,
+                    &row.total_reserves,
+                    &row.utilization,
+                    &row.supply_apy,
+                    &row.borrow_apy,
+                ],
+            )
+            .await
+            .with_context(|| format!("Failed to write history database {}", self.location))?;
+
+        Ok(())
+    }
+
+    async fn latest(&self, limit: usize, market: Option<&str>) -> Result<Vec<HistoryRow>> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let rows = self.client
+            .query(
+                "SELECT to_char(timestamp AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"'), market, symbol,
+                    available_liquidity::text, total_borrows::text, total_reserves::text,
+                    utilization, supply_apy, borrow_apy
+                 FROM readings
+                 WHERE $1::text IS NULL OR lower(market) = lower($1::text)
+                 ORDER BY id DESC
+                 LIMIT $2",
+                &[&market, &limit],
+            )
+            .await
+            .with_context(|| format!("Failed to query history database {}", self.location))?;
+
+        let mut rows = rows
+            .iter()
+            .map(|row| Ok(HistoryRow {
+                timestamp: row.try_get(0)?,
+                market: row.try_get(1)?,
+                symbol: row.try_get(2)?,
+                available_liquidity: row.try_get(3)?,
+                total_borrows: row.try_get(4)?,
+                total_reserves: row.try_get(5)?,
+                utilization: row.try_get(6)?,
+                supply_apy: row.try_get(7)?,
+                borrow_apy: row.try_get(8)?,
+            }))
+            .collect::<Result<Vec<_>, tokio_postgres::Error>>()
+            .with_context(|| format!("Failed to read history database {}", self.location))?;
+
+        rows.reverse();
+        Ok(rows)
+    }
+}
@@ -1,11 +1,30 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use ethers::prelude::*;
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::sync::Arc;
-use std::time::Duration;
-use tracing::{error, info, warn};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+mod fallback;
+mod history;
+mod revert;
+mod secret;
+mod server;
+mod signer;
+mod txlog;
+
+use fallback::FallbackRpc;
+use history::{History, HistoryRow};
+use secret::Secret;
+use signer::TxSigner;
+use txlog::{TxLog, TxOperation, TxRecord, TxStatus};
 
 // Compound V2 cToken ABI methods
 abigen!(
@@ -15,6 +34,21 @@ abigen!(
         function totalBorrows() external view returns (uint256)
         function totalReserves() external view returns (uint256)
         function symbol() external view returns (string)
+        function totalSupply() external view returns (uint256)
+        function exchangeRateStored() external view returns (uint256)
+        function comptroller() external view returns (address)
+        function underlying() external view returns (address)
+        function mint(uint256 mintAmount) external returns (uint256)
+        function redeemUnderlying(uint256 redeemAmount) external returns (uint256)
+    ]"#,
+);
+
+// Compound V2 Comptroller cap methods (a cap of 0 means unlimited)
+abigen!(
+    Comptroller,
+    r#"[
+        function borrowCaps(address cToken) external view returns (uint256)
+        function supplyCaps(address cToken) external view returns (uint256)
     ]"#,
 );
 
@@ -29,9 +63,47 @@ abigen!(
         function getUtilization() external view returns (uint256)
         function baseToken() external view returns (address)
         function supply(address asset, uint256 amount) external
+        function supplyTo(address dst, address asset, uint256 amount) external
         function withdraw(address asset, uint256 amount) external
+        function withdrawTo(address to, address asset, uint256 amount) external
         function getSupplyRate(uint256 utilization) external view returns (uint64)
         function getBorrowRate(uint256 utilization) external view returns (uint64)
+        function baseTokenPriceFeed() external view returns (address)
+        function getPrice(address priceFeed) external view returns (uint256)
+        function baseScale() external view returns (uint256)
+        function borrowBalanceOf(address account) external view returns (uint256)
+        function numAssets() external view returns (uint8)
+        function getAssetInfo(uint8 i) external view returns ((uint8, address, address, uint64, uint64, uint64, uint64, uint128))
+        function collateralBalanceOf(address account, address asset) external view returns (uint128)
+    ]"#,
+);
+
+// Signature variants found on some Compound V3 forks, tried when the
+// canonical Comet method reverts
+abigen!(
+    CometCompat,
+    r#"[
+        function totalBorrows() external view returns (uint256)
+        function getSupplyRate() external view returns (uint64)
+        function getBorrowRate() external view returns (uint64)
+    ]"#,
+);
+
+// Compound V3 rewards. getRewardOwed accrues before returning, so it is not
+// view, but it can be read with eth_call.
+abigen!(
+    CometRewards,
+    r#"[
+        function getRewardOwed(address comet, address account) external returns ((address, uint256))
+    ]"#,
+);
+
+// Chainlink price feed (AggregatorV3Interface)
+abigen!(
+    ChainlinkAggregator,
+    r#"[
+        function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+        function decimals() external view returns (uint8)
     ]"#,
 );
 
@@ -47,571 +119,7495 @@ abigen!(
     ]"#,
 );
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 enum CompoundVersion {
+    #[default]
     V2,
     V3,
 }
 
-impl Default for CompoundVersion {
-    fn default() -> Self {
-        CompoundVersion::V2
-    }
+/// Unit in which liquidity_threshold is expressed
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ThresholdUnit {
+    /// Token base units (default)
+    #[default]
+    Base,
+    /// Whole US dollars, valued with the protocol's price oracle
+    Usd,
 }
 
-#[derive(Parser, Debug)]
-#[command(name = "compound-monitor")]
-#[command(about = "Monitor and interact with Compound Finance markets", long_about = None)]
-struct Cli {
-    #[command(subcommand)]
-    command: Option<Commands>,
+/// Where alerts are delivered
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum NotificationBackend {
+    /// POST to webhook_url (default)
+    #[default]
+    Webhook,
+    /// sendMessage through a Telegram bot
+    Telegram,
+    /// Plain-text email over SMTP
+    Email,
 }
 
-#[derive(Subcommand, Debug)]
-enum Commands {
-    /// Monitor liquidity (default mode)
-    Monitor,
-    /// Deposit (supply) assets to Compound
-    Supply {
-        /// Amount to supply (in base units, e.g., 1000000 = 1 USDC)
-        #[arg(short, long)]
-        amount: String,
-        /// Private key for signing transactions (optional if set in config.json)
-        #[arg(short, long)]
-        private_key: Option<String>,
-    },
-    /// Withdraw assets from Compound
-    Withdraw {
-        /// Amount to withdraw (in base units, e.g., 1000000 = 1 USDC)
-        #[arg(short, long)]
-        amount: String,
-        /// Private key for signing transactions (optional if set in config.json)
-        #[arg(short, long)]
-        private_key: Option<String>,
-    },
-    /// Check your balance
-    Balance {
-        /// Wallet address to check (optional if using monitor_address.json)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+/// What signs transactions
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SignerKind {
+    /// A private key: --private-key, COMPOUND_PRIVATE_KEY, keystore_path or
+    /// private_key (default)
+    #[default]
+    Key,
+    /// A Ledger hardware wallet connected over USB
+    Ledger,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct Config {
-    #[serde(default)]
-    compound_version: CompoundVersion,
-    rpc_url: String,
-    market_address: String,
-    market_name: Option<String>,
-    webhook_url: String,
-    poll_interval_secs: u64,
-    liquidity_threshold: String,
-    notification_enabled: Option<bool>,
-    /// Optional private key for transactions (keep this secure!)
-    private_key: Option<String>,
+/// How the email backend secures its SMTP connection
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS (default, usually port 587)
+    #[default]
+    Starttls,
+    /// TLS from the start (usually port 465)
+    Tls,
+    /// Unencrypted, e.g. a local relay
+    None,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct MonitorAddress {
-    name: String,
-    address: String,
+/// Shape of the body POSTed to webhook_url
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum WebhookType {
+    /// The alert JSON itself, shaped by alert_fields (default)
+    #[default]
+    Generic,
+    /// A Discord message with one embed
+    Discord,
+    /// A Slack message in Block Kit format
+    Slack,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct MonitorAddressConfig {
-    addresses: Vec<MonitorAddress>,
+/// How urgent an alert is, which picks its emoji and color in chat messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Critical,
+    Warning,
+    /// Any recovery, whatever the alert type
+    Recovered,
 }
 
-impl MonitorAddressConfig {
-    fn load() -> Result<Self> {
-        let config_path = "monitor_address.json";
-
-        let config_content = fs::read_to_string(config_path)
-            .context("Failed to read monitor_address.json. Make sure it exists in the current directory.")?;
+impl Severity {
+    /// Built-in severity of an alert type: critical when funds are at risk now
+    fn of(alert_type: AlertType) -> Self {
+        match alert_type {
+            AlertType::Liquidity | AlertType::AccountHealth | AlertType::NegativeReserves | AlertType::RapidDrain => Severity::Critical,
+            AlertType::SupplyCap
+            | AlertType::BorrowCap
+            | AlertType::SignerGas
+            | AlertType::AccountingDivergence
+            | AlertType::SupplyApy
+            | AlertType::BorrowApy => Severity::Warning,
+        }
+    }
 
-        let config: MonitorAddressConfig = serde_json::from_str(&config_content)
-            .context("Failed to parse monitor_address.json. Check JSON syntax.")?;
+    fn default_emoji(self) -> &'static str {
+        match self {
+            Severity::Critical => "🔴",
+            Severity::Warning => "🟡",
+            Severity::Recovered => "🟢",
+        }
+    }
 
-        Ok(config)
+    fn default_color(self) -> u32 {
+        match self {
+            Severity::Critical => 0xE74C3C,
+            Severity::Warning => 0xF1C40F,
+            Severity::Recovered => 0x2ECC71,
+        }
     }
 }
 
-#[derive(Debug, Serialize)]
-struct LiquidityAlert {
-    market_address: String,
-    market_symbol: String,
-    available_liquidity: String,
-    total_borrows: String,
-    total_reserves: String,
-    threshold: String,
-    timestamp: i64,
-    message: String,
+/// Overrides of a severity's look in chat messages
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct SeverityStyle {
+    /// Prepended to the title; "" for none
+    emoji: Option<String>,
+    /// Embed/attachment color as "#RRGGBB"
+    color: Option<String>,
 }
 
-impl Config {
-    fn load() -> Result<Self> {
-        let config_path = "config.json";
+/// Parse a "#RRGGBB" color
+fn parse_color(color: &str) -> Result<u32> {
+    color.strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .with_context(|| format!("Invalid color {:?}, expected \"#RRGGBB\"", color))
+}
 
-        info!("Loading configuration from {}", config_path);
+/// What the liquidity alert compares against its threshold
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ThresholdMode {
+    /// Available liquidity below liquidity_threshold (default)
+    #[default]
+    Liquidity,
+    /// Utilization above utilization_threshold_pct
+    Utilization,
+}
 
-        let config_content = fs::read_to_string(config_path)
-            .context("Failed to read config.json. Make sure it exists in the current directory.")?;
+/// liquidity_threshold: either a single value, or a default value plus time
+/// windows (UTC) in which a different value applies
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum LiquidityThreshold {
+    Fixed(String),
+    Scheduled {
+        default: String,
+        schedule: Vec<ThresholdWindow>,
+    },
+}
 
-        let config: Config = serde_json::from_str(&config_content)
-            .context("Failed to parse config.json. Check JSON syntax.")?;
+impl LiquidityThreshold {
+    fn default_value(&self) -> &str {
+        match self {
+            Self::Fixed(value) | Self::Scheduled { default: value, .. } => value,
+        }
+    }
 
-        Ok(config)
+    /// Whether any value is in whole tokens ("1000.5") rather than base units,
+    /// and so needs the token's decimals to parse
+    fn is_decimal(&self) -> bool {
+        let windows = match self {
+            Self::Fixed(_) => &[][..],
+            Self::Scheduled { schedule, .. } => schedule,
+        };
+        self.default_value().contains('.') || windows.iter().any(|window| window.value.contains('.'))
     }
 }
 
-struct CompoundMonitor {
-    config: Config,
-    provider: Arc<Provider<Http>>,
-    client: reqwest::Client,
-    threshold: U256,
+/// Parse a threshold: base units ("1000000000"), or whole tokens with a
+/// decimal point ("1000.5") scaled by the token's `decimals`
+fn parse_amount(value: &str, decimals: Option<u8>) -> Result<U256> {
+    if !value.contains('.') {
+        return U256::from_dec_str(value).with_context(|| format!("Invalid threshold {:?}", value));
+    }
+
+    let decimals = decimals.with_context(|| format!(
+        "Threshold {:?} has a decimal point but the token's decimals are unknown \
+         (decimal thresholds need threshold_unit \"base\")",
+        value
+    ))?;
+    // parse_units would silently truncate the extra digits, or wrap a negative amount
+    let fraction = value.split_once('.').map_or("", |(_, fraction)| fraction);
+    if fraction.len() > decimals as usize {
+        anyhow::bail!("Threshold {:?} has more fractional digits than the token's {} decimals", value, decimals);
+    }
+    if value.starts_with('-') {
+        anyhow::bail!("Invalid threshold {:?}", value);
+    }
+    let amount = ethers::utils::parse_units(value, decimals as u32)
+        .with_context(|| format!("Invalid threshold {:?} for a token with {} decimals", value, decimals))?;
+    Ok(amount.into())
 }
 
-impl CompoundMonitor {
-    async fn new(config: Config) -> Result<Self> {
-        let provider = Provider::<Http>::try_from(&config.rpc_url)
-            .context("Failed to create provider")?;
-        let provider = Arc::new(provider);
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct ThresholdWindow {
+    /// Days the window applies to, e.g. ["mon", "fri"] (default: every day)
+    #[serde(default)]
+    days: Vec<String>,
+    /// Start time (UTC), "HH:MM"
+    start: String,
+    /// End time (UTC), "HH:MM"; earlier than start for windows spanning midnight
+    end: String,
+    value: String,
+}
 
-        let client = reqwest::Client::new();
+/// A parsed ThresholdWindow
+#[derive(Debug, Clone, PartialEq)]
+struct ScheduledThreshold {
+    days: Vec<chrono::Weekday>,
+    start: chrono::NaiveTime,
+    end: chrono::NaiveTime,
+    value: U256,
+}
 
-        let threshold = U256::from_dec_str(&config.liquidity_threshold)
-            .context("Invalid liquidity threshold")?;
+impl ScheduledThreshold {
+    fn parse(window: &ThresholdWindow, decimals: Option<u8>) -> Result<Self> {
+        let days = window.days.iter()
+            .map(|day| day.parse::<chrono::Weekday>()
+                .map_err(|_| anyhow::anyhow!("Invalid day {:?}", day)))
+            .collect::<Result<_>>()?;
+        let parse_time = |time: &str| chrono::NaiveTime::parse_from_str(time, "%H:%M")
+            .with_context(|| format!("Invalid time {:?}, expected HH:MM", time));
 
         Ok(Self {
-            config,
-            provider,
-            client,
-            threshold,
+            days,
+            start: parse_time(&window.start)?,
+            end: parse_time(&window.end)?,
+            value: parse_amount(&window.value, decimals)?,
         })
     }
 
-    async fn check_liquidity(&self) -> Result<(U256, U256, U256, String)> {
-        let address: H160 = self.config.market_address.parse()
-            .context("Invalid market address")?;
+    fn is_active(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        use chrono::{Datelike, Duration as ChronoDuration};
 
-        match self.config.compound_version {
-            CompoundVersion::V2 => self.check_liquidity_v2(address).await,
-            CompoundVersion::V3 => self.check_liquidity_v3(address).await,
-        }
+        let time = now.time();
+        let (in_window, day) = if self.start <= self.end {
+            (self.start <= time && time < self.end, now.weekday())
+        } else if time >= self.start {
+            (true, now.weekday())
+        } else {
+            // After midnight in a window that started the previous day
+            (time < self.end, (now - ChronoDuration::days(1)).weekday())
+        };
+
+        in_window && (self.days.is_empty() || self.days.contains(&day))
     }
+}
 
-    async fn check_liquidity_v2(&self, address: H160) -> Result<(U256, U256, U256, String)> {
-        let contract = CToken::new(address, Arc::clone(&self.provider));
+/// Parsed liquidity_threshold: the default value and any schedule windows
+#[derive(Debug, Clone, PartialEq)]
+struct Thresholds {
+    default: U256,
+    schedule: Vec<ScheduledThreshold>,
+}
 
-        // Get available cash (liquidity)
-        let cash = contract.get_cash().call().await
-            .context("Failed to get cash (V2)")?;
+impl Thresholds {
+    /// `decimals` is the token's, needed for values in whole tokens
+    fn parse(threshold: &LiquidityThreshold, decimals: Option<u8>) -> Result<Self> {
+        let default = parse_amount(threshold.default_value(), decimals)
+            .context("Invalid liquidity threshold")?;
 
-        // Get total borrows
-        let borrows = contract.total_borrows().call().await
-            .context("Failed to get total borrows (V2)")?;
+        let schedule = match threshold {
+            LiquidityThreshold::Fixed(_) => Vec::new(),
+            LiquidityThreshold::Scheduled { schedule, .. } => schedule.iter()
+                .map(|window| ScheduledThreshold::parse(window, decimals))
+                .collect::<Result<_>>()
+                .context("Invalid liquidity_threshold schedule")?,
+        };
 
-        // Get total reserves
-        let reserves = contract.total_reserves().call().await
-            .context("Failed to get total reserves (V2)")?;
+        Ok(Self { default, schedule })
+    }
 
-        // Get market symbol
-        let symbol = contract.symbol().call().await
-            .context("Failed to get symbol (V2)")?;
+    /// The first active schedule window's value, otherwise the default
+    fn at(&self, now: chrono::DateTime<chrono::Utc>) -> U256 {
+        self.schedule.iter()
+            .find(|window| window.is_active(now))
+            .map_or(self.default, |window| window.value)
+    }
+}
 
-        info!(
-            "Market: {} | Available Liquidity: {} | Borrows: {} | Reserves: {}",
-            symbol, cash, borrows, reserves
-        );
+/// Resolves once SIGHUP is received (never on platforms without it)
+struct ReloadSignal {
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+}
 
-        Ok((cash, borrows, reserves, symbol))
+impl ReloadSignal {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            hangup: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .context("Failed to install SIGHUP handler")?,
+        })
     }
 
-    async fn check_liquidity_v3(&self, address: H160) -> Result<(U256, U256, U256, String)> {
-        let contract = Comet::new(address, Arc::clone(&self.provider));
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.hangup.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
 
-        // Get the base token address (e.g., USDC)
-        let base_token_address = contract.base_token().call().await
-            .context("Failed to get base token address (V3)")?;
+/// How long shutdown waits for in-flight snapshot exports
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
-        // Get the actual balance of base token held by the Comet contract
-        let base_token = ERC20::new(base_token_address, Arc::clone(&self.provider));
-        let contract_balance = base_token.balance_of(address).call().await
-            .context("Failed to get contract balance (V3)")?;
+/// Resolves once SIGINT (Ctrl-C) or SIGTERM is received, with the signal's name
+struct ShutdownSignal {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
 
-        // Get total supply (total assets supplied to the protocol)
-        let total_supply = contract.total_supply().call().await
-            .context("Failed to get total supply (V3)")?;
+impl ShutdownSignal {
+    fn new() -> Result<Self> {
+        #[cfg(unix)]
+        use tokio::signal::unix::{signal, SignalKind};
 
-        // Get total borrows
-        let total_borrow = contract.total_borrow().call().await
-            .context("Failed to get total borrow (V3)")?;
+        Ok(Self {
+            #[cfg(unix)]
+            interrupt: signal(SignalKind::interrupt()).context("Failed to install SIGINT handler")?,
+            #[cfg(unix)]
+            terminate: signal(SignalKind::terminate()).context("Failed to install SIGTERM handler")?,
+        })
+    }
 
-        // Get reserves (can be negative in V3)
-        let reserves_i256 = contract.get_reserves().call().await
-            .context("Failed to get reserves (V3)")?;
+    async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+            "Ctrl-C"
+        }
+    }
+}
 
-        // Convert I256 to U256 (take absolute value if negative)
-        let reserves = if reserves_i256 >= I256::zero() {
-            U256::from(reserves_i256.as_u128())
-        } else {
-            U256::zero()
-        };
+/// Accept either a single value or a list, e.g. `"rpc_url": "..."` or
+/// `"rpc_urls": [...]`
+fn one_or_many<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
 
-        // Available liquidity is the actual balance of base token in the contract
-        let available_liquidity = contract_balance;
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
 
-        // Get utilization for APY calculation
-        let utilization = contract.get_utilization().call().await
-            .context("Failed to get utilization (V3)")?;
+/// Render the last reading of every market in the Prometheus text format
+fn render_metrics(states: &HashMap<String, MarketState>) -> String {
+    type Gauge = (&'static str, &'static str, fn(&MarketSnapshot) -> Option<f64>);
+    const GAUGES: &[Gauge] = &[
+        ("compound_available_liquidity", "Available liquidity in base token units",
+            |snapshot| Some(u256_to_f64(snapshot.available_liquidity))),
+        ("compound_total_borrows", "Total borrows in base token units",
+            |snapshot| Some(u256_to_f64(snapshot.total_borrows))),
+        ("compound_total_reserves", "Total reserves in base token units (negative when the protocol owes more than it holds)",
+            |snapshot| snapshot.reserves.to_string().parse().ok()),
+        ("compound_supply_apy_percent", "Supply APY in percent (V3)", |snapshot| snapshot.supply_apy),
+        ("compound_borrow_apy_percent", "Borrow APY in percent (V3)", |snapshot| snapshot.borrow_apy),
+        ("compound_utilization_percent", "Utilization in percent", |snapshot| snapshot.utilization),
+    ];
 
-        // Get supply and borrow rates
-        let supply_rate = contract.get_supply_rate(utilization).call().await
-            .context("Failed to get supply rate (V3)")?;
-        let borrow_rate = contract.get_borrow_rate(utilization).call().await
-            .context("Failed to get borrow rate (V3)")?;
+    let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
 
-        // Calculate APY from rates
-        // Rates are per second with 18 decimals (1e18 = 100% per second)
-        let supply_apy = self.calculate_apy(supply_rate);
-        let borrow_apy = self.calculate_apy(borrow_rate);
+    let mut markets: Vec<(&String, &MarketSnapshot)> = states
+        .iter()
+        .filter_map(|(market, state)| Some((market, state.last_snapshot.as_ref()?)))
+        .collect();
+    markets.sort_unstable_by_key(|(market, _)| *market);
 
-        let symbol = self.config.market_name.clone()
-            .unwrap_or_else(|| "cUSDCv3".to_string());
+    let mut output = String::new();
+    for (name, help, value) in GAUGES {
+        output.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name));
+        for (market, snapshot) in &markets {
+            if let Some(value) = value(snapshot) {
+                output.push_str(&format!(
+                    "{}{{market=\"{}\",symbol=\"{}\"}} {}\n",
+                    name, escape(market), escape(&snapshot.symbol), value
+                ));
+            }
+        }
+    }
+    output
+}
 
-        info!(
-            "Market: {} | Available Liquidity: {} | Total Supply: {} | Total Borrow: {} | Reserves: {}",
-            symbol, available_liquidity, total_supply, total_borrow, reserves
-        );
-        // Convert utilization to percentage (utilization is scaled by 1e18)
-        let utilization_pct = utilization.as_u128() as f64 / 1e16;
+/// Append `value` to `path` as one line of newline-delimited JSON
+fn append_json_line(path: &str, value: &impl Serialize) -> Result<()> {
+    use std::io::Write;
 
-        info!(
-            "Supply APY: {:.2}% | Borrow APY: {:.2}% | Utilization: {:.2}%",
-            supply_apy, borrow_apy, utilization_pct
-        );
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path))?;
 
-        Ok((available_liquidity, total_borrow, reserves, symbol))
-    }
+    writeln!(file, "{}", serde_json::to_string(value)?)
+        .with_context(|| format!("Failed to write {}", path))?;
 
-    async fn send_alert(&self, alert: LiquidityAlert) -> Result<()> {
-        info!("Sending alert to webhook: {}", self.config.webhook_url);
+    Ok(())
+}
 
-        let response = self.client
-            .post(&self.config.webhook_url)
-            .json(&alert)
-            .send()
-            .await
-            .context("Failed to send webhook request")?;
+/// Lossy conversion for display and ratio math; values beyond f64 range saturate
+fn u256_to_f64(value: U256) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(f64::MAX)
+}
 
-        if response.status().is_success() {
-            info!("Alert sent successfully");
-        } else {
-            warn!("Alert sent but received non-success status: {}", response.status());
-        }
+/// Signed counterpart of u256_to_f64, for reserves
+fn i256_to_f64(value: I256) -> f64 {
+    let magnitude = u256_to_f64(value.unsigned_abs());
+    if value.is_negative() { -magnitude } else { magnitude }
+}
 
-        Ok(())
+/// V2 reserves are unsigned; anything beyond I256::MAX is absurd anyway and
+/// is clamped rather than wrapped negative
+fn signed_reserves(reserves: U256) -> I256 {
+    I256::try_from(reserves).unwrap_or(I256::MAX)
+}
+
+/// `numerator / denominator` as a percentage, or None if the denominator is
+/// zero. The ratio is scaled in a U512 so values near U256::MAX can't overflow.
+fn percentage(numerator: U256, denominator: U256) -> Option<f64> {
+    if denominator.is_zero() {
+        return None;
     }
 
-    async fn supply_v3(&self, amount: U256, private_key: &str) -> Result<()> {
-        info!("Supplying {} to Compound V3...", amount);
+    // Twelve digits of precision: 1e12 = 100.0000000000%
+    let scaled = numerator.full_mul(U256::exp10(12)) / U512::from(denominator);
+    let scaled = scaled.to_string().parse::<f64>().unwrap_or(f64::MAX);
+    Some(scaled / 1e10)
+}
 
-        let wallet = private_key.parse::<LocalWallet>()
-            .context("Invalid private key")?;
-        let wallet = wallet.with_chain_id(1u64); // Mainnet
+/// `a * b / divisor` computed in a U512 so the product can't overflow, or None
+/// if the divisor is zero or the result doesn't fit in a U256
+fn mul_div(a: U256, b: U256, divisor: U256) -> Option<U256> {
+    if divisor.is_zero() {
+        return None;
+    }
+    U256::try_from(a.full_mul(b) / U512::from(divisor)).ok()
+}
 
-        let provider = Provider::<Http>::try_from(&self.config.rpc_url)?;
-        let client = SignerMiddleware::new(provider, wallet);
-        let client = Arc::new(client);
+/// Parse a config file as TOML (`.toml`), YAML (`.yaml`/`.yml`) or JSON
+/// (anything else)
+fn parse_config<T: serde::de::DeserializeOwned>(path: &str, content: &str) -> Result<T> {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("toml") => toml::from_str(content)
+            .with_context(|| format!("Failed to parse {}. Check TOML syntax.", path)),
+        Some("yaml" | "yml") => serde_yaml::from_str(content)
+            .with_context(|| format!("Failed to parse {}. Check YAML syntax.", path)),
+        _ => serde_json::from_str(content)
+            .with_context(|| format!("Failed to parse {}. Check JSON syntax.", path)),
+    }
+}
 
-        let market_address: H160 = self.config.market_address.parse()?;
-        let contract = Comet::new(market_address, client.clone());
+/// Format of the log output
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum LogFormat {
+    /// Human-readable lines (default)
+    #[default]
+    Text,
+    /// One JSON object per line, with readings as separate fields
+    Json,
+}
 
-        // Get base token address
-        let base_token_address = contract.base_token().call().await?;
-        let base_token = ERC20::new(base_token_address, client.clone());
+impl LogFormat {
+    /// log_format from config.json, read ahead of Config::load so logging is
+    /// set up before the config is loaded (and its errors logged)
+    fn from_config_file(path: &str) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| parse_config::<serde_json::Value>(path, &content).ok())
+            .and_then(|config| serde_json::from_value(config.get("log_format")?.clone()).ok())
+            .unwrap_or_default()
+    }
+}
 
-        // Check allowance
-        let allowance = base_token.allowance(client.address(), market_address).call().await?;
+/// How the alert `timestamp` field is serialized in webhook payloads
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum TimestampFormat {
+    /// Unix epoch seconds as an integer (default)
+    #[default]
+    Unix,
+    /// RFC3339 string, e.g. "2023-11-09T21:20:00+00:00"
+    Rfc3339,
+}
 
-        if allowance < amount {
-            info!("Approving Compound to spend tokens...");
-            let approve_tx = base_token.approve(market_address, U256::MAX);
-            let pending_tx = approve_tx.send().await?;
-            let receipt = pending_tx.await?.context("Approve transaction failed")?;
-            info!("Approved! Transaction hash: {:?}", receipt.transaction_hash);
+impl TimestampFormat {
+    fn format(&self, time: chrono::DateTime<chrono::Utc>) -> AlertTimestamp {
+        match self {
+            TimestampFormat::Unix => AlertTimestamp::Unix(time.timestamp()),
+            TimestampFormat::Rfc3339 => AlertTimestamp::Rfc3339(time.to_rfc3339()),
         }
+    }
+}
 
-        // Supply to Compound
-        info!("Sending supply transaction...");
-        let supply_tx = contract.supply(base_token_address, amount);
-        let pending_tx = supply_tx.send().await?;
-        let receipt = pending_tx.await?.context("Supply transaction failed")?;
+/// How utilization is shown in logs
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum UtilizationFormat {
+    /// Percentage, e.g. 85.47% (default)
+    #[default]
+    Percent,
+    /// Ratio, e.g. 0.8547
+    Ratio,
+    /// Basis points, e.g. 8547 bps
+    Bps,
+}
 
-        info!("✓ Supply successful!");
-        info!("Transaction hash: {:?}", receipt.transaction_hash);
-        info!("Gas used: {:?}", receipt.gas_used);
+/// How large integer values are rendered in logs
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum NumberFormat {
+    /// Decimal, e.g. 1000000 (default)
+    #[default]
+    Decimal,
+    /// 0x-prefixed hex, as in raw RPC responses, e.g. 0xf4240
+    Hex,
+}
 
-        Ok(())
+impl NumberFormat {
+    fn format(&self, value: U256) -> String {
+        match self {
+            NumberFormat::Decimal => value.to_string(),
+            NumberFormat::Hex => format!("{:#x}", value),
+        }
     }
+}
 
-    async fn withdraw_v3(&self, amount: U256, private_key: &str) -> Result<()> {
-        info!("Withdrawing {} from Compound V3...", amount);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum AlertTimestamp {
+    Unix(i64),
+    Rfc3339(String),
+}
 
-        let wallet = private_key.parse::<LocalWallet>()
-            .context("Invalid private key")?;
-        let wallet = wallet.with_chain_id(1u64); // Mainnet
+impl AlertTimestamp {
+    fn to_datetime(&self) -> Result<chrono::DateTime<chrono::Utc>> {
+        match self {
+            AlertTimestamp::Unix(secs) => chrono::DateTime::from_timestamp(*secs, 0)
+                .with_context(|| format!("Timestamp out of range: {}", secs)),
+            AlertTimestamp::Rfc3339(time) => chrono::DateTime::parse_from_rfc3339(time)
+                .map(|time| time.with_timezone(&chrono::Utc))
+                .with_context(|| format!("Invalid timestamp: {}", time)),
+        }
+    }
+}
 
-        let provider = Provider::<Http>::try_from(&self.config.rpc_url)?;
-        let client = SignerMiddleware::new(provider, wallet);
-        let client = Arc::new(client);
+#[derive(Parser, Debug)]
+#[command(name = "compound-monitor")]
+#[command(about = "Monitor and interact with Compound Finance markets", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Market to use instead of market_address: an alias from market_aliases or an address
+    #[arg(long, global = true)]
+    market: Option<String>,
+    /// Write a JSON summary of the command's outcome to this file on exit ("-" for stderr)
+    #[arg(long, global = true)]
+    summary_file: Option<String>,
+    /// Configuration file
+    #[arg(long, global = true, default_value = "config.json")]
+    config: String,
+    /// Address list for batch balance checks
+    #[arg(long, global = true, default_value = "monitor_address.json")]
+    monitor_addresses: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Monitor liquidity (default mode)
+    Monitor,
+    /// Poll every configured market once and exit: code 0 if all are above
+    /// threshold, 2 if any is below, 1 on errors
+    Check,
+    /// Deposit (supply) assets to Compound
+    Supply {
+        /// Amount to supply (in base units, e.g., 1000000 = 1 USDC)
+        #[arg(short, long)]
+        amount: String,
+        /// Private key for signing transactions (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+        /// Bypass the max_tx_amount safety limit and the min_tx_interval_secs throttle
+        #[arg(long)]
+        force: bool,
+        /// Refuse to resend if a transaction with this key was already sent
+        #[arg(long)]
+        idempotency_key: Option<String>,
+        /// Simulate the transaction (gas estimate and eth_call) without sending it
+        #[arg(long)]
+        dry_run: bool,
+        /// Don't ask for confirmation of an amount above confirm_above_amount
+        #[arg(short, long)]
+        yes: bool,
+        /// Nonce for the transaction (default: the account's pending nonce);
+        /// a needed approval uses this one and the transaction the next
+        #[arg(long)]
+        nonce: Option<u64>,
+    },
+    /// Withdraw assets from Compound
+    Withdraw {
+        /// Amount to withdraw (in base units, e.g., 1000000 = 1 USDC), or "max"
+        /// for the entire Compound balance (V3)
+        #[arg(short, long)]
+        amount: String,
+        /// Private key for signing transactions (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+        /// Bypass the max_tx_amount safety limit and the min_tx_interval_secs throttle
+        #[arg(long)]
+        force: bool,
+        /// Refuse to resend if a transaction with this key was already sent
+        #[arg(long)]
+        idempotency_key: Option<String>,
+        /// Simulate the transaction (gas estimate and eth_call) without sending it
+        #[arg(long)]
+        dry_run: bool,
+        /// Don't ask for confirmation of an amount above confirm_above_amount
+        #[arg(short, long)]
+        yes: bool,
+        /// Nonce for the transaction (default: the account's pending nonce);
+        /// a needed approval uses this one and the transaction the next
+        #[arg(long)]
+        nonce: Option<u64>,
+    },
+    /// Borrow the base asset from Compound V3 against posted collateral
+    Borrow {
+        /// Amount to borrow (in base units, e.g., 1000000 = 1 USDC)
+        #[arg(short, long)]
+        amount: String,
+        /// Private key for signing transactions (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+        /// Bypass the max_tx_amount safety limit and the min_tx_interval_secs throttle
+        #[arg(long)]
+        force: bool,
+        /// Refuse to resend if a transaction with this key was already sent
+        #[arg(long)]
+        idempotency_key: Option<String>,
+        /// Simulate the transaction (gas estimate and eth_call) without sending it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check your balance
+    Balance {
+        /// Wallet address to check (optional if using monitor_address.json)
+        #[arg(short, long)]
+        address: Option<String>,
+        /// Check every configured V3 market (market_address and market_aliases),
+        /// grouped by base token
+        #[arg(long)]
+        all_markets: bool,
+        /// Print the balances as JSON on stdout (logs go to stderr)
+        #[arg(long)]
+        json: bool,
+        /// After the addresses in monitor_address.json, print the total wallet
+        /// and Compound balance across all of them
+        #[arg(long)]
+        summary: bool,
+    },
+    /// Show the ERC20 allowance granted to the market (V3)
+    Allowance {
+        /// Owner address to check (defaults to the signer of --private-key or config.json)
+        #[arg(short, long)]
+        address: Option<String>,
+        /// Private key whose address is checked (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+    },
+    /// Show an account's collateral, debt and health ratio (V3)
+    HealthCheck {
+        /// Account to check (defaults to the signer of --private-key or config.json)
+        #[arg(short, long)]
+        address: Option<String>,
+        /// Private key whose address is checked (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+    },
+    /// Compare supplies and withdrawals in the transaction log with the current
+    /// Compound balance to compute yield (V3)
+    Accounting {
+        /// Address to account for (defaults to the signer of --private-key or config.json)
+        #[arg(short, long)]
+        address: Option<String>,
+        /// Private key whose address is accounted for (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+    },
+    /// Execute supply/withdraw operations listed in a JSON or CSV file (V3)
+    BatchTx {
+        /// File of operations: a JSON array or a CSV with `op,market,amount,to`
+        /// columns and an optional `idempotency_key` column
+        #[arg(short, long)]
+        file: String,
+        /// Private key for signing transactions (optional if set in config.json)
+        #[arg(short, long)]
+        private_key: Option<Secret>,
+        /// Execute without asking for confirmation before each operation
+        #[arg(long)]
+        yes: bool,
+        /// Keep going after a failed operation instead of stopping
+        #[arg(long)]
+        continue_on_error: bool,
+        /// Bypass the max_tx_amount safety limit and the min_tx_interval_secs throttle
+        #[arg(long)]
+        force: bool,
+    },
+    /// Replay recorded snapshots through the alert logic and report which polls
+    /// would have alerted, without sending anything
+    Backtest {
+        /// JSONL file of snapshots, e.g. written by a file:// snapshot_sink_url
+        #[arg(short, long, default_value = "snapshots.jsonl")]
+        input: String,
+    },
+    /// Check liquidity for a list of markets from a CSV and write the results
+    Scan {
+        /// Input CSV with `address,version` columns
+        #[arg(short, long, default_value = "markets.csv")]
+        input: String,
+        /// Output CSV for the results
+        #[arg(short, long, default_value = "scan_results.csv")]
+        output: String,
+    },
+    /// List the known Compound V3 markets on the RPC's chain, with their base
+    /// tokens read on-chain
+    Discover,
+    /// Print the most recent readings from the history database (database_path)
+    History {
+        /// Number of readings to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print the effective configuration as JSON, with secrets redacted
+    ShowConfig,
+    /// Check that the webhook URL is reachable (DNS, TCP, TLS, HTTP status)
+    TestWebhook,
+    /// Send a synthetic alert through the configured notification backend,
+    /// formatted as a real one, without reading the market
+    TestAlert,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Config {
+    /// Market passed to select_market, re-applied when the config is reloaded
+    #[serde(skip)]
+    selected_market: Option<String>,
+    #[serde(default)]
+    compound_version: CompoundVersion,
+    /// RPC endpoints, the first preferred; a failing endpoint is dropped for the
+    /// next. A single rpc_url string is also accepted.
+    #[serde(alias = "rpc_url", deserialize_with = "one_or_many")]
+    rpc_urls: Vec<Secret>,
+    /// Retries for a failed RPC read while checking a market, with exponential
+    /// backoff (default: 2)
+    rpc_max_retries: Option<u32>,
+    /// Delay before the first RPC retry, doubling each time (default: 500)
+    rpc_retry_base_ms: Option<u64>,
+    /// Single market to monitor; optional when `markets` is given, in which case
+    /// it defaults to the first entry for the non-monitor commands
+    #[serde(default)]
+    market_address: String,
+    market_name: Option<String>,
+    /// Markets to monitor each poll. When omitted, the single market above is
+    /// monitored.
+    #[serde(default)]
+    markets: Vec<MarketConfig>,
+    /// Settings inherited by every entry of `markets` that doesn't set its own
+    #[serde(default)]
+    defaults: MarketDefaults,
+    /// Friendly names for markets, usable with --market or as market_address
+    #[serde(default)]
+    market_aliases: HashMap<String, MarketAlias>,
+    /// Required unless set in `defaults` or on every market
+    #[serde(default)]
+    webhook_url: Secret,
+    /// Where alerts go (default: webhook)
+    #[serde(default)]
+    notification_backend: NotificationBackend,
+    /// Bot token for the telegram backend
+    telegram_bot_token: Option<Secret>,
+    /// Chat the telegram backend sends to: a numeric id or @channelname
+    telegram_chat_id: Option<String>,
+    /// SMTP server for the email backend
+    smtp_host: Option<String>,
+    /// Default: 587, or 465 with smtp_security "tls", or 25 with "none"
+    smtp_port: Option<u16>,
+    #[serde(default)]
+    smtp_security: SmtpSecurity,
+    smtp_username: Option<String>,
+    smtp_password: Option<Secret>,
+    /// Sender of alert emails, e.g. "Compound Monitor <monitor@example.com>"
+    email_from: Option<String>,
+    /// Recipients of alert emails
+    #[serde(default)]
+    email_to: Vec<String>,
+    /// Format of the webhook body (default: generic)
+    #[serde(default)]
+    webhook_type: WebhookType,
+    /// Emoji and color of each severity in Discord, Slack and Telegram messages,
+    /// overriding the built-in ones
+    #[serde(default)]
+    severity_styles: HashMap<Severity, SeverityStyle>,
+    /// Severity of alert types, overriding the built-in one
+    #[serde(default)]
+    alert_severities: HashMap<AlertType, Severity>,
+    /// Optional check of the webhook response body; by default any 2xx status
+    /// counts as delivered
+    webhook_ack: Option<WebhookAck>,
+    /// Alert fields to send, in this order (default: the full payload)
+    alert_fields: Option<Vec<String>>,
+    /// Alert fields to drop from the payload
+    #[serde(default)]
+    alert_exclude_fields: Vec<String>,
+    /// Number of recent readings to include in alerts as recent_history
+    /// (default: 0, disabled)
+    #[serde(default)]
+    alert_history_size: usize,
+    /// Retries for an undelivered alert, with exponential backoff (default: 2)
+    webhook_max_retries: Option<u32>,
+    /// Timeout for each webhook request; a timed-out attempt is retried. Also
+    /// bounds threshold_url and snapshot sink requests (default: 10)
+    webhook_timeout_secs: Option<u64>,
+    /// Random +/- percentage applied to each webhook retry delay so a fleet of
+    /// monitors doesn't retry in lockstep (default: 25, 0 disables)
+    webhook_retry_jitter_pct: Option<f64>,
+    poll_interval_secs: u64,
+    /// Read only this many markets each poll_interval_secs (or block), taking
+    /// turns round-robin, instead of each market on its own interval
+    markets_per_tick: Option<usize>,
+    /// Threshold for markets without their own; required unless every entry in
+    /// `markets` sets one (or threshold_mode is "utilization")
+    liquidity_threshold: Option<LiquidityThreshold>,
+    /// Alert on low liquidity ("liquidity", default) or high utilization ("utilization")
+    #[serde(default)]
+    threshold_mode: ThresholdMode,
+    /// Utilization percentage above which to alert when threshold_mode is "utilization"
+    utilization_threshold_pct: Option<f64>,
+    /// Unit of liquidity_threshold: "base" (token base units, default) or "usd"
+    #[serde(default)]
+    threshold_unit: ThresholdUnit,
+    /// Optional endpoint serving a JSON map of market -> threshold, fetched every
+    /// poll and overriding liquidity_threshold
+    threshold_url: Option<Secret>,
+    /// Read the base token price and log USD values, without changing the
+    /// threshold unit. Priced by price_feed_address, or the Comet oracle on V3.
+    usd_pricing: Option<bool>,
+    /// Chainlink USD price feed for the market's underlying token, used instead
+    /// of the Comet oracle and required for USD values on V2
+    price_feed_address: Option<String>,
+    notification_enabled: Option<bool>,
+    /// Send a notification when liquidity recovers after a breach (default: true)
+    recovery_alerts: Option<bool>,
+    /// Once an alert is sent for a market, suppress further alerts of the same
+    /// type for it for this many seconds (default: 0, disabled). A liquidity
+    /// recovery resets it.
+    alert_cooldown_secs: Option<u64>,
+    /// Suppress an alert identical to one sent within this many seconds: same
+    /// market, type and liquidity bucket (default: 0, disabled)
+    dedup_window_secs: Option<u64>,
+    /// Width of the liquidity buckets compared by dedup_window_secs, in base
+    /// units (default: 1, exact liquidity)
+    dedup_bucket_size: Option<String>,
+    /// Seconds after startup during which checks run but alerts are suppressed (default: 0)
+    #[serde(default)]
+    startup_grace_secs: u64,
+    /// Format of the alert timestamp: "unix" (default) or "rfc3339"
+    #[serde(default)]
+    timestamp_format: TimestampFormat,
+    /// Log output: "text" (default) or "json"
+    #[serde(default)]
+    log_format: LogFormat,
+    /// Format of liquidity/borrows/reserves values in logs: "decimal" (default) or "hex"
+    #[serde(default)]
+    log_number_format: NumberFormat,
+    /// Decimal places for APY and utilization in logs (default: 2)
+    rate_decimals: Option<usize>,
+    /// How utilization is shown in logs: "percent" (default), "ratio" or "bps"
+    #[serde(default)]
+    utilization_format: UtilizationFormat,
+    /// CometRewards contract, for the rewards owed shown by `balance` (V3)
+    rewards_address: Option<String>,
+    /// Optional private key for transactions, in plaintext; prefer keystore_path
+    private_key: Option<Secret>,
+    /// Private key from the COMPOUND_PRIVATE_KEY environment variable, which
+    /// takes precedence over keystore_path and private_key
+    #[serde(skip)]
+    env_private_key: Option<Secret>,
+    /// File this configuration was loaded from (--config)
+    #[serde(skip)]
+    config_path: String,
+    /// Decimals of the current market's token, when a decimal threshold needs them
+    #[serde(skip)]
+    token_decimals: Option<u8>,
+    /// Address list for batch balance checks (--monitor-addresses)
+    #[serde(skip)]
+    monitor_addresses_path: String,
+    /// Encrypted JSON keystore holding the signing key, used instead of private_key
+    keystore_path: Option<String>,
+    /// Password for keystore_path (default: the KEYSTORE_PASSWORD environment variable)
+    keystore_password: Option<Secret>,
+    /// "key" (default) to sign with a private key, or "ledger" to sign on a
+    /// Ledger hardware wallet
+    #[serde(default)]
+    signer: SignerKind,
+    /// Ledger Live account index of the Ledger account, i.e. m/44'/60'/INDEX'/0/0
+    /// (default: 0)
+    ledger_index: Option<usize>,
+    /// Full derivation path of the Ledger account, instead of ledger_index,
+    /// e.g. "m/44'/60'/0'/0" for the legacy (MEW/MyCrypto) layout
+    ledger_derivation_path: Option<String>,
+    /// Chain id transactions are signed for (default: detected from the RPC,
+    /// falling back to 1)
+    chain_id: Option<u64>,
+    /// Refuse to start unless the RPC reports this chain id, e.g. 1 for mainnet
+    expected_chain_id: Option<u64>,
+    /// Warn when the signer's native ETH balance is below this amount, e.g. "0.05"
+    min_signer_eth_balance: Option<String>,
+    /// Also check the signer's ETH balance each monitor tick and alert when low
+    /// (signer taken from signer_address or private_key)
+    monitor_signer_gas: Option<bool>,
+    /// Signer address for the per-tick gas check when no private key is configured
+    signer_address: Option<String>,
+    /// Minimum supply/withdraw amount in base units (refuses dust transactions)
+    min_tx_amount: Option<String>,
+    /// Maximum supply/withdraw amount in base units (bypass with --force)
+    max_tx_amount: Option<String>,
+    /// Hard limits on a single supply / withdrawal in base units, not
+    /// overridden by --force
+    max_supply_amount: Option<String>,
+    max_withdraw_amount: Option<String>,
+    /// Ask for confirmation before supplying or withdrawing more than this
+    /// many base units (skip with --yes)
+    confirm_above_amount: Option<String>,
+    /// Warn when a borrow would bring debt to this percentage of the
+    /// liquidation limit (default: 80)
+    borrow_warning_pct: Option<f64>,
+    /// Alert when an account's health ratio (liquidation limit / debt) drops
+    /// below this, e.g. 1.2
+    health_threshold: Option<f64>,
+    /// Borrower accounts whose health is checked each monitor tick (V3)
+    #[serde(default)]
+    health_addresses: Vec<String>,
+    /// EIP-1559 fee cap for transactions, in gwei (default: estimated by the node)
+    max_fee_per_gas: Option<f64>,
+    /// EIP-1559 priority fee (tip) for transactions, in gwei (default: estimated)
+    max_priority_fee_per_gas: Option<f64>,
+    /// Blocks a sent transaction must have (including its own) before it is
+    /// treated as final (default: 1)
+    confirmations: Option<usize>,
+    /// Serve Prometheus metrics for every market on this port at /metrics
+    metrics_port: Option<u16>,
+    /// Serve a liveness endpoint on this port at /health (may equal metrics_port)
+    health_port: Option<u16>,
+    /// Optional endpoint that receives every snapshot each tick, independent of alerting;
+    /// a file:// URL appends them to a plain JSONL file (no checksum or compression)
+    snapshot_sink_url: Option<Secret>,
+    /// Retries for a failed snapshot export, with exponential backoff (default: 3)
+    snapshot_sink_max_retries: Option<u32>,
+    /// Block explorer base URLs keyed by chain id, overriding the built-in presets
+    #[serde(default)]
+    explorer_urls: HashMap<u64, String>,
+    /// Refuse a supply/withdraw if another was sent within this many seconds,
+    /// unless --force is given (tracked in the transaction log)
+    min_tx_interval_secs: Option<u64>,
+    /// Optional JSON file persisting per-market low/high-water marks across restarts
+    water_marks_path: Option<String>,
+    /// Path of the JSONL log of sent transactions (default: tx_log.jsonl)
+    tx_log_path: Option<String>,
+    /// Optional database recording every successful reading, for the `history`
+    /// command and charting: a SQLite file path, or a postgres:// URL
+    #[serde(alias = "database_url")]
+    database_path: Option<Secret>,
+    /// Optional JSONL file recording every alert decision, including suppressed
+    /// alerts and why
+    decision_log_path: Option<String>,
+    /// Maximum number of concurrent RPC requests for bulk operations, and of
+    /// markets read at once while monitoring (default: 10)
+    max_concurrent_requests: Option<usize>,
+    /// Per-address timeout for batch balance checks (default: 30)
+    balance_timeout_secs: Option<u64>,
+    /// Alert when total supply/borrows come within this percentage of the market's
+    /// supply/borrow cap (V2 only, e.g. 5.0 alerts at 95% of the cap)
+    cap_headroom_pct: Option<f64>,
+    /// Alert when supply APY (percent) falls below this (V3 only)
+    supply_apy_min: Option<f64>,
+    /// Alert when borrow APY (percent) rises above this (V3 only)
+    borrow_apy_max: Option<f64>,
+    /// Alert when reserves are negative, i.e. the protocol is in deficit
+    /// (V3 only, default: false)
+    negative_reserves_alert: Option<bool>,
+    /// Alert when available liquidity falls by more than this percentage between
+    /// two consecutive polls, even while still above the threshold
+    liquidity_drop_pct: Option<f64>,
+    /// Successful readings of a market to collect before liquidity_drop_pct is
+    /// evaluated, so startup readings don't alert without a stable baseline
+    /// (default: 1, the one previous reading a drop needs)
+    warmup_reads: Option<u64>,
+    /// Compare the token balance with the protocol's accounting
+    /// (totalSupply - totalBorrow + reserves) each poll and log when they differ
+    /// by more than this percentage (V3 only)
+    accounting_tolerance_pct: Option<f64>,
+    /// Also send an alert when accounting_tolerance_pct is exceeded
+    #[serde(default)]
+    accounting_alert: bool,
+    /// Optional boolean expression evaluated against each snapshot instead of the
+    /// simple threshold comparison, e.g. "liquidity < 1e12 && utilization > 90"
+    alert_expression: Option<String>,
+}
+
+/// One entry of `markets`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct MarketConfig {
+    /// Market address, or an alias from market_aliases
+    address: String,
+    name: Option<String>,
+    /// Defaults to the top-level compound_version
+    version: Option<CompoundVersion>,
+    /// Per-market overrides, falling back to `defaults`, then the top-level field
+    liquidity_threshold: Option<LiquidityThreshold>,
+    utilization_threshold_pct: Option<f64>,
+    notification_enabled: Option<bool>,
+    webhook_url: Option<Secret>,
+    alert_cooldown_secs: Option<u64>,
+    poll_interval_secs: Option<u64>,
+    /// Chainlink USD price feed for this market's underlying token
+    price_feed_address: Option<String>,
+    /// Decimals of the market's token, fetched at startup for decimal thresholds
+    #[serde(skip)]
+    token_decimals: Option<u8>,
+}
+
+/// The `defaults` block: per-market settings applied to every market that
+/// doesn't override them
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct MarketDefaults {
+    liquidity_threshold: Option<LiquidityThreshold>,
+    utilization_threshold_pct: Option<f64>,
+    notification_enabled: Option<bool>,
+    webhook_url: Option<Secret>,
+    alert_cooldown_secs: Option<u64>,
+    poll_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct MarketAlias {
+    address: String,
+    #[serde(default)]
+    version: CompoundVersion,
+    /// Display name, defaults to the alias itself
+    name: Option<String>,
+}
+
+/// Expected webhook response body for an alert to count as delivered
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct WebhookAck {
+    /// Substring the response body must contain
+    contains: Option<String>,
+    /// Dotted path of a JSON field in the response body, e.g. "result.status"
+    json_field: Option<String>,
+    /// Value the JSON field must equal. When omitted the field must be present
+    /// and not null/false
+    equals: Option<String>,
+}
+
+impl WebhookAck {
+    fn matches(&self, body: &str) -> bool {
+        if let Some(ref needle) = self.contains {
+            if !body.contains(needle.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(ref path) = self.json_field {
+            let Ok(json) = serde_json::from_str::<serde_json::Value>(body) else {
+                return false;
+            };
+            let field = path.split('.').try_fold(&json, |value, key| value.get(key));
+
+            let matched = match (field, &self.equals) {
+                (None, _) => false,
+                (Some(serde_json::Value::String(actual)), Some(expected)) => actual == expected,
+                // Non-string fields (numbers, booleans) compare by JSON value
+                (Some(actual), Some(expected)) => serde_json::from_str::<serde_json::Value>(expected)
+                    .is_ok_and(|expected| expected == *actual),
+                (Some(actual), None) => !matches!(actual, serde_json::Value::Null | serde_json::Value::Bool(false)),
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MonitorAddress {
+    name: String,
+    address: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MonitorAddressConfig {
+    addresses: Vec<MonitorAddress>,
+}
+
+impl MonitorAddressConfig {
+    /// Load the address list: a `.csv` file with `name,address` columns, or JSON
+    fn load(config_path: &str) -> Result<Self> {
+        let is_csv = std::path::Path::new(config_path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        if is_csv {
+            return Self::load_csv(config_path);
+        }
+
+        let config_content = fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}. Make sure it exists, or pass --monitor-addresses.", config_path))?;
+
+        let config: MonitorAddressConfig = serde_json::from_str(&config_content)
+            .with_context(|| format!("Failed to parse {}. Check JSON syntax.", config_path))?;
+
+        Ok(config)
+    }
+
+    fn load_csv(config_path: &str) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(config_path)
+            .with_context(|| format!("Failed to read {}. Make sure it exists, or pass --monitor-addresses.", config_path))?;
+        let addresses = reader
+            .deserialize::<MonitorAddress>()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to parse {}. Expected `name,address` columns.", config_path))?;
+
+        Ok(MonitorAddressConfig { addresses })
+    }
+}
+
+/// Per-invocation options for supply/withdraw transactions
+#[derive(Debug, Default)]
+struct TxOptions {
+    /// Bypass the max_tx_amount safety limit and the min_tx_interval_secs throttle
+    force: bool,
+    /// Refuse to resend if a transaction with this key was already sent
+    idempotency_key: Option<String>,
+    /// Credit the supply / send the withdrawal to this address instead of the
+    /// signer (supplyTo/withdrawTo)
+    to: Option<H160>,
+    /// Simulate the approval and transaction instead of sending them
+    dry_run: bool,
+    /// Skip the confirm_above_amount prompt
+    yes: bool,
+    /// Nonce of the first transaction sent (--nonce), instead of the pending nonce
+    nonce: Option<U256>,
+}
+
+/// One operation in a batch transaction file
+#[derive(Debug, Deserialize)]
+struct BatchOperation {
+    op: TxOperation,
+    /// Market alias or address (default: the configured market)
+    market: Option<String>,
+    /// Amount in base units
+    amount: String,
+    /// Recipient, if not the signer
+    to: Option<String>,
+    /// Refuse to send if a transaction with this key was already sent, so a
+    /// batch can be rerun after a failure without repeating what went through
+    #[serde(default)]
+    idempotency_key: Option<String>,
+}
+
+/// A single liquidity reading for a market
+#[derive(Debug, Clone)]
+struct MarketSnapshot {
+    symbol: String,
+    available_liquidity: U256,
+    total_borrows: U256,
+    /// Reserves as reported by the protocol; V3 reserves go negative when the
+    /// protocol owes more than it holds
+    reserves: I256,
+    /// Total supplied to the protocol, in base/underlying units (for V2, the
+    /// cToken supply converted at the stored exchange rate)
+    total_supply: Option<U256>,
+    /// Utilization in percent (V3 only)
+    utilization: Option<f64>,
+    /// Supply APY in percent (V3 only)
+    supply_apy: Option<f64>,
+    /// Borrow APY in percent (V3 only)
+    borrow_apy: Option<f64>,
+    /// Base token price in USD from the protocol oracle (V3 with USD pricing)
+    base_price_usd: Option<f64>,
+    /// Available liquidity valued in USD (V3 with USD pricing)
+    available_liquidity_usd: Option<f64>,
+    /// Comptroller supply cap in underlying units (V2 only, when cap alerts are enabled)
+    supply_cap: Option<U256>,
+    /// Comptroller borrow cap in underlying units (V2 only, when cap alerts are enabled)
+    borrow_cap: Option<U256>,
+}
+
+/// Optional integrations, probed once when monitoring starts. A feature that
+/// is unavailable on the chain or market is disabled with a single warning
+/// instead of failing every poll.
+#[derive(Debug, Clone, Copy)]
+struct Capabilities {
+    /// Comet oracle pricing of the base token (V3)
+    usd_pricing: bool,
+    /// Comptroller supplyCaps (V2)
+    supply_caps: bool,
+    /// Comptroller borrowCaps (V2)
+    borrow_caps: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self { usd_pricing: true, supply_caps: true, borrow_caps: true }
+    }
+}
+
+/// An address's base token balances in one V3 market
+#[derive(Debug, Clone)]
+struct BalanceReading {
+    base_token: H160,
+    symbol: String,
+    decimals: u8,
+    wallet_balance: U256,
+    compound_balance: U256,
+    /// Claimable rewards, when rewards_address is configured and readable
+    rewards: Option<RewardReading>,
+}
+
+/// Balances summed over the addresses of a batch, for `balance --summary`
+#[derive(Debug)]
+struct BalanceTotals {
+    symbol: String,
+    decimals: u8,
+    addresses: usize,
+    wallet_balance: U256,
+    compound_balance: U256,
+}
+
+impl BalanceTotals {
+    fn new(reading: &BalanceReading) -> Self {
+        Self {
+            symbol: reading.symbol.clone(),
+            decimals: reading.decimals,
+            addresses: 0,
+            wallet_balance: U256::zero(),
+            compound_balance: U256::zero(),
+        }
+    }
+
+    fn add(&mut self, reading: &BalanceReading) {
+        self.addresses += 1;
+        self.wallet_balance = self.wallet_balance.saturating_add(reading.wallet_balance);
+        self.compound_balance = self.compound_balance.saturating_add(reading.compound_balance);
+    }
+}
+
+/// A V3 account's debt against its collateral, in USD
+#[derive(Debug, Clone)]
+struct BorrowPosition {
+    debt_usd: f64,
+    /// Debt the collateral supports (borrowCollateralFactor)
+    borrow_limit_usd: f64,
+    /// Debt above which the account can be liquidated (liquidateCollateralFactor)
+    liquidation_limit_usd: f64,
+    base_price_usd: f64,
+    base_scale: U256,
+    /// Collateral assets with a non-zero balance
+    collateral: Vec<CollateralPosition>,
+}
+
+impl BorrowPosition {
+    /// Liquidation limit over debt: below 1 the account can be liquidated.
+    /// None when there is no debt.
+    fn health_ratio(&self) -> Option<f64> {
+        (self.debt_usd > 0.0).then(|| self.liquidation_limit_usd / self.debt_usd)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CollateralPosition {
+    asset: H160,
+    balance: u128,
+    scale: u64,
+    value_usd: f64,
+}
+
+/// An account's health for account_health alerts
+#[derive(Debug, Clone, Serialize)]
+struct AccountHealth {
+    address: String,
+    health_ratio: f64,
+    debt_usd: f64,
+    liquidation_limit_usd: f64,
+    health_threshold: f64,
+}
+
+/// Reward tokens owed to an account by the CometRewards contract
+#[derive(Debug, Clone)]
+struct RewardReading {
+    token: H160,
+    symbol: String,
+    decimals: u8,
+    owed: U256,
+}
+
+/// Lowest and highest available liquidity seen for a market
+#[derive(Debug, Clone, Copy)]
+struct WaterMarks {
+    low: U256,
+    high: U256,
+}
+
+/// Water marks as persisted in water_marks_path, keyed by market address
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedWaterMarks {
+    low_water_mark: String,
+    high_water_mark: String,
+}
+
+/// Per-market state tracked across poll ticks
+#[derive(Debug, Default)]
+struct MarketState {
+    water_marks: Option<WaterMarks>,
+    /// Threshold fetched from threshold_url, replacing liquidity_threshold
+    threshold_override: Option<U256>,
+    /// Threshold in effect at the last poll, to log changes
+    active_threshold: Option<U256>,
+    /// The last alert_history_size readings, oldest first
+    history: VecDeque<SnapshotEntry>,
+    /// Whether the liquidity alert condition held at the last poll
+    in_breach: bool,
+    /// The last successful reading, served as metrics
+    last_snapshot: Option<MarketSnapshot>,
+    /// When each type of alert was last delivered, for alert_cooldown_secs,
+    /// keyed by type and subject (see alert_subject)
+    last_alert_sent: HashMap<(AlertType, String), Instant>,
+    /// Available liquidity at the previous successful poll and when it was
+    /// read, for liquidity_drop_pct
+    previous_liquidity: Option<(U256, Instant)>,
+    /// Successful readings since startup, or since the poll interval last
+    /// changed, for warmup_reads
+    reads: u64,
+}
+
+/// Serializable form of a snapshot, as exported to the snapshot sink
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotEntry {
+    market_address: String,
+    market_symbol: String,
+    available_liquidity: String,
+    total_borrows: String,
+    total_reserves: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_supply: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    utilization: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supply_apy: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    borrow_apy: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_price_usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_liquidity_usd: Option<f64>,
+    timestamp: AlertTimestamp,
+}
+
+impl SnapshotEntry {
+    /// The reading as a MarketSnapshot, for replaying through the alert logic
+    fn to_snapshot(&self) -> Result<MarketSnapshot> {
+        let parse = |value: &str| U256::from_dec_str(value)
+            .with_context(|| format!("Invalid value {:?}", value));
+        let reserves = I256::from_dec_str(&self.total_reserves)
+            .with_context(|| format!("Invalid value {:?}", self.total_reserves))?;
+
+        Ok(MarketSnapshot {
+            symbol: self.market_symbol.clone(),
+            available_liquidity: parse(&self.available_liquidity)?,
+            total_borrows: parse(&self.total_borrows)?,
+            reserves,
+            total_supply: self.total_supply.as_deref().map(parse).transpose()?,
+            utilization: self.utilization,
+            supply_apy: self.supply_apy,
+            borrow_apy: self.borrow_apy,
+            base_price_usd: self.base_price_usd,
+            available_liquidity_usd: self.available_liquidity_usd,
+            supply_cap: None,
+            borrow_cap: None,
+        })
+    }
+}
+
+/// A known Comet market found by the discover command
+#[derive(Debug, Serialize)]
+struct DiscoveredMarket {
+    name: String,
+    address: String,
+    /// Base token address and symbol, None when they couldn't be read
+    base_token: Option<String>,
+    base_symbol: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanMarket {
+    address: String,
+    version: CompoundVersion,
+}
+
+#[derive(Debug, Serialize)]
+struct ScanResult {
+    address: String,
+    version: String,
+    available_liquidity: String,
+    total_borrows: String,
+    total_reserves: String,
+    error: String,
+}
+
+/// Outcome of an alert decision
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Decision {
+    /// Delivered to the webhook
+    Sent,
+    /// Not sent on purpose; `reason` says why
+    Suppressed,
+    /// Delivery was attempted and failed
+    Failed,
+}
+
+/// One line of the decision log
+#[derive(Debug, Serialize)]
+struct DecisionEvent {
+    timestamp: AlertTimestamp,
+    market_address: String,
+    alert_type: AlertType,
+    decision: Decision,
+    reason: String,
+    message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AlertType {
+    /// Liquidity below threshold (or alert_expression matched)
+    Liquidity,
+    SupplyCap,
+    BorrowCap,
+    /// Signer's native balance too low to pay for gas
+    SignerGas,
+    /// Token balance and protocol accounting disagree (V3)
+    AccountingDivergence,
+    /// A borrower's health ratio is below health_threshold (V3)
+    AccountHealth,
+    /// Supply APY below supply_apy_min (V3)
+    SupplyApy,
+    /// Borrow APY above borrow_apy_max (V3)
+    BorrowApy,
+    /// Reserves below zero, with negative_reserves_alert (V3)
+    NegativeReserves,
+    /// Liquidity fell by more than liquidity_drop_pct since the previous poll
+    RapidDrain,
+}
+
+/// How close a market is to one of its caps
+#[derive(Debug, Clone, Serialize)]
+struct CapUsage {
+    current: String,
+    cap: String,
+    headroom: String,
+    usage_pct: f64,
+}
+
+/// A fall in available liquidity between two consecutive polls
+#[derive(Debug, Clone, Serialize)]
+struct LiquidityDrop {
+    from: String,
+    to: String,
+    drop_pct: f64,
+    elapsed_secs: u64,
+}
+
+/// Signer native balance for gas alerts, in wei
+#[derive(Debug, Clone, Serialize)]
+struct SignerBalance {
+    address: String,
+    balance: String,
+    minimum: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LiquidityAlert {
+    alert_type: AlertType,
+    market_address: String,
+    /// Name of the market that triggered the alert, when configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    market_name: Option<String>,
+    market_symbol: String,
+    available_liquidity: String,
+    total_borrows: String,
+    total_reserves: String,
+    /// liquidity_threshold, or utilization_threshold_pct in utilization mode
+    threshold: String,
+    /// Utilization in percent, when known for the market
+    #[serde(skip_serializing_if = "Option::is_none")]
+    utilization: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_price_usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_liquidity_usd: Option<f64>,
+    timestamp: AlertTimestamp,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cap_usage: Option<CapUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signer_balance: Option<SignerBalance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_health: Option<AccountHealth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    liquidity_drop: Option<LiquidityDrop>,
+    /// Supply and borrow APY in percent, when known for the market
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supply_apy: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    borrow_apy: Option<f64>,
+    /// Whether total_reserves is below zero (V3 deficit)
+    reserves_negative: bool,
+    /// True for the notification that a liquidity breach has cleared
+    recovered: bool,
+    /// The readings leading up to this alert, oldest first (alert_history_size)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    recent_history: Vec<SnapshotEntry>,
+    /// Lowest available liquidity seen for this market
+    #[serde(skip_serializing_if = "Option::is_none")]
+    low_water_mark: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    high_water_mark: Option<String>,
+}
+
+/// One-line heading for chat notifications, e.g. "cUSDCv3: liquidity alert"
+fn alert_title(alert: &LiquidityAlert) -> String {
+    let kind = match alert.alert_type {
+        _ if alert.recovered => "recovered",
+        AlertType::Liquidity => "liquidity alert",
+        AlertType::SupplyCap => "supply cap alert",
+        AlertType::BorrowCap => "borrow cap alert",
+        AlertType::SignerGas => "signer gas alert",
+        AlertType::AccountingDivergence => "accounting divergence",
+        AlertType::AccountHealth => "account health alert",
+        AlertType::SupplyApy => "supply APY alert",
+        AlertType::BorrowApy => "borrow APY alert",
+        AlertType::NegativeReserves => "negative reserves alert",
+        AlertType::RapidDrain => "rapid drain alert",
+    };
+    match alert.market_name {
+        Some(ref name) => format!("{} ({}): {}", alert.market_symbol, name, kind),
+        None => format!("{}: {}", alert.market_symbol, kind),
+    }
+}
+
+/// What an alert is about within its market and type, so alerts about
+/// different subjects have separate cooldowns: the account of an account
+/// health alert, otherwise empty
+fn alert_subject(alert: &LiquidityAlert) -> String {
+    alert.account_health.as_ref().map(|health| health.address.to_lowercase()).unwrap_or_default()
+}
+
+/// Record a problem for Config::validate unless `value` is an address
+fn check_address(problems: &mut Vec<String>, field: &str, value: &str) {
+    if value.parse::<H160>().is_err() {
+        problems.push(format!("{}: {:?} is not a valid address", field, value));
+    }
+}
+
+/// Record a problem for Config::validate unless `url` parses with one of `schemes`
+fn check_url(problems: &mut Vec<String>, field: &str, url: &Secret, schemes: &[&str]) {
+    match reqwest::Url::parse(url.expose()) {
+        Ok(parsed) if schemes.contains(&parsed.scheme()) => {}
+        Ok(parsed) => problems.push(format!(
+            "{}: unsupported scheme {:?} in {} (expected {})",
+            field, parsed.scheme(), url, schemes.join(", ")
+        )),
+        Err(e) => problems.push(format!("{}: {} is not a valid URL ({})", field, url, e)),
+    }
+}
+
+/// Escape the characters Telegram's legacy Markdown treats as markup
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '_' | '*' | '`' | '[') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The numbers worth showing in a chat notification, as (label, value) pairs
+fn alert_summary(alert: &LiquidityAlert) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("Available liquidity", alert.available_liquidity.clone()),
+        ("Total borrows", alert.total_borrows.clone()),
+        ("Threshold", alert.threshold.clone()),
+    ];
+    if let Some(utilization) = alert.utilization {
+        fields.push(("Utilization", format!("{:.2}%", utilization)));
+    }
+    if let Some(usd) = alert.available_liquidity_usd {
+        fields.push(("Liquidity (USD)", format!("${:.2}", usd)));
+    }
+    if matches!(alert.alert_type, AlertType::SupplyApy | AlertType::BorrowApy) {
+        if let Some(apy) = alert.supply_apy {
+            fields.push(("Supply APY", format!("{:.2}%", apy)));
+        }
+        if let Some(apy) = alert.borrow_apy {
+            fields.push(("Borrow APY", format!("{:.2}%", apy)));
+        }
+    }
+    if alert.alert_type == AlertType::NegativeReserves {
+        fields.push(("Total reserves", alert.total_reserves.clone()));
+    }
+    if let Some(ref drop) = alert.liquidity_drop {
+        fields.push(("Drop", format!("{:.2}% in {}s (from {})", drop.drop_pct, drop.elapsed_secs, drop.from)));
+    }
+    fields.push(("Market", alert.market_address.clone()));
+    fields
+}
+
+/// Block explorer base URLs for well-known chains
+fn default_explorer_url(chain_id: u64) -> Option<&'static str> {
+    match chain_id {
+        1 => Some("https://etherscan.io"),
+        10 => Some("https://optimistic.etherscan.io"),
+        137 => Some("https://polygonscan.com"),
+        8453 => Some("https://basescan.org"),
+        42161 => Some("https://arbiscan.io"),
+        534352 => Some("https://scrollscan.com"),
+        11155111 => Some("https://sepolia.etherscan.io"),
+        _ => None,
+    }
+}
+
+/// Known Compound V3 (Comet) deployments as (chain id, market, Comet address)
+const KNOWN_COMET_MARKETS: &[(u64, &str, &str)] = &[
+    (1, "cUSDCv3", "0xc3d688B66703497DAA19211EEdff47f25384cdc3"),
+    (1, "cWETHv3", "0xA17581A9E3356d9A858b789D68B4d866e593aE94"),
+    (1, "cUSDTv3", "0x3Afdc9BCA9213A35503b077a6072F3D0d5AB0840"),
+    (1, "cwstETHv3", "0x3D0bb1ccaB520A66e607822fC55BC921738fAFE3"),
+    (10, "cUSDCv3", "0x2e44e174f7D53F0212823acC11C01A11d58c5bCB"),
+    (10, "cUSDTv3", "0x995E394b8B2437aC8Ce61Ee0bC610D617962B214"),
+    (10, "cWETHv3", "0xE36A30D249f7761327fd973001A32010b521b6Fd"),
+    (137, "cUSDCv3", "0xF25212E676D1F7F89Cd72fFEe66158f541246445"),
+    (137, "cUSDTv3", "0xaeB318360f27748Acb200CE616E389A6C9409a07"),
+    (8453, "cUSDCv3", "0xb125E6687d4313864e53df431d5425969c15Eb2F"),
+    (8453, "cUSDbCv3", "0x9c4ec768c28520B50860ea7a15bd7213a9fF58bf"),
+    (8453, "cWETHv3", "0x46e6b214b524310239732D51387075E0e70970bf"),
+    (42161, "cUSDCv3", "0x9c4ec768c28520B50860ea7a15bd7213a9fF58bf"),
+    (42161, "cUSDC.ev3", "0xA5EDBDD9646f8dFF606d7448e414884C7d905dCA"),
+    (42161, "cWETHv3", "0x6f7D514bbD4aFf3BcD1140B7344b32f063dEe486"),
+    (42161, "cUSDTv3", "0xd98Be00b5D27fc98112BdE293e487f8D4cA57d07"),
+    (534352, "cUSDCv3", "0xB2f97c1Bd3bf02f5e74d13f02E3e26F93D77CE44"),
+    (11155111, "cUSDCv3", "0xAec1F48e02Cfb822Be958B68C7957156EB3F0b6e"),
+    (11155111, "cWETHv3", "0x2943ac1216979aD8dB76D9147F64E61adc126e96"),
+];
+
+impl Config {
+    /// Replace any secret values embedded in `text` (e.g. RPC URLs inside
+    /// transport errors) with their redacted form
+    fn redact(&self, text: &str) -> String {
+        let secrets = [
+            Some(&self.webhook_url),
+            self.private_key.as_ref(),
+            self.env_private_key.as_ref(),
+            self.keystore_password.as_ref(),
+            self.snapshot_sink_url.as_ref(),
+            self.threshold_url.as_ref(),
+            self.defaults.webhook_url.as_ref(),
+            self.telegram_bot_token.as_ref(),
+            self.smtp_password.as_ref(),
+        ];
+        let market_webhooks = self.markets.iter().map(|market| market.webhook_url.as_ref());
+        let rpc_urls = self.rpc_urls.iter().map(Some);
+
+        let mut text = text.to_string();
+        for secret in secrets.into_iter().chain(market_webhooks).chain(rpc_urls).flatten() {
+            if !secret.expose().is_empty() {
+                text = text.replace(secret.expose(), &secret.to_string());
+            }
+        }
+        text
+    }
+
+    /// Check the formats that would otherwise only fail deep inside a command
+    /// (addresses, URLs, amounts, thresholds, the poll interval), reporting
+    /// every problem at once
+    fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if self.poll_interval_secs == 0 {
+            problems.push("poll_interval_secs must be greater than 0".to_string());
+        }
+        if self.markets_per_tick == Some(0) {
+            problems.push("markets_per_tick must be greater than 0".to_string());
+        }
+
+        for url in &self.rpc_urls {
+            check_url(&mut problems, "rpc_url", url, &["http", "https", "ws", "wss"]);
+        }
+        if !self.webhook_url.expose().is_empty() {
+            check_url(&mut problems, "webhook_url", &self.webhook_url, &["http", "https", "file"]);
+        }
+        if let Some(ref url) = self.defaults.webhook_url {
+            check_url(&mut problems, "defaults.webhook_url", url, &["http", "https", "file"]);
+        }
+        if let Some(ref url) = self.snapshot_sink_url {
+            check_url(&mut problems, "snapshot_sink_url", url, &["http", "https", "file"]);
+        }
+        if let Some(ref url) = self.threshold_url {
+            check_url(&mut problems, "threshold_url", url, &["http", "https"]);
+        }
+
+        // A single-market config is resolved into markets[0] from the top-level fields
+        let single = self.markets.len() == 1 && self.markets[0].address == self.market_address;
+        for (index, market) in self.markets.iter().enumerate() {
+            let field = |name: &str| match single {
+                true if name == "address" => "market_address".to_string(),
+                true => name.to_string(),
+                false => format!("markets[{}].{}", index, name),
+            };
+            check_address(&mut problems, &field("address"), &market.address);
+            // An interval inherited from the top level was already checked above
+            if market.poll_interval_secs == Some(0) && self.poll_interval_secs != 0 {
+                problems.push(format!("{} must be greater than 0", field("poll_interval_secs")));
+            }
+            if let Some(ref address) = market.price_feed_address {
+                check_address(&mut problems, &field("price_feed_address"), address);
+            }
+            // Inherited webhooks were already checked above
+            let inherited = [Some(&self.webhook_url), self.defaults.webhook_url.as_ref()];
+            if let Some(url) = market.webhook_url.as_ref().filter(|url| !inherited.contains(&Some(*url))) {
+                check_url(&mut problems, &field("webhook_url"), url, &["http", "https", "file"]);
+            }
+            // Decimal thresholds are parsed once the token's decimals are fetched
+            if let Some(threshold) = market.liquidity_threshold.as_ref().filter(|threshold| !threshold.is_decimal()) {
+                if let Err(e) = Thresholds::parse(threshold, None) {
+                    problems.push(format!("{}: {:#}", field("liquidity_threshold"), e));
+                }
+            }
+        }
+        for (severity, style) in &self.severity_styles {
+            if let Some(Err(e)) = style.color.as_deref().map(parse_color) {
+                let severity = serde_json::to_value(severity)?;
+                problems.push(format!("severity_styles.{}.color: {:#}", severity.as_str().unwrap_or_default(), e));
+            }
+        }
+        for (alias, market) in &self.market_aliases {
+            check_address(&mut problems, &format!("market_aliases.{}.address", alias), &market.address);
+        }
+
+        let addresses = [
+            ("rewards_address", &self.rewards_address),
+            ("signer_address", &self.signer_address),
+        ];
+        for (field, address) in addresses {
+            if let Some(address) = address {
+                check_address(&mut problems, field, address);
+            }
+        }
+        for address in &self.health_addresses {
+            check_address(&mut problems, "health_addresses", address);
+        }
+
+        let amounts = [
+            ("min_tx_amount", &self.min_tx_amount),
+            ("max_tx_amount", &self.max_tx_amount),
+            ("max_supply_amount", &self.max_supply_amount),
+            ("max_withdraw_amount", &self.max_withdraw_amount),
+            ("confirm_above_amount", &self.confirm_above_amount),
+            ("dedup_bucket_size", &self.dedup_bucket_size),
+        ];
+        for (field, amount) in amounts {
+            if let Some(amount) = amount {
+                if U256::from_dec_str(amount).is_err() {
+                    problems.push(format!("{}: {:?} is not a whole number of base units", field, amount));
+                }
+            }
+        }
+        if let Some(drop_pct) = self.liquidity_drop_pct {
+            if !(drop_pct > 0.0 && drop_pct <= 100.0) {
+                problems.push(format!("liquidity_drop_pct: {} is not a percentage above 0 and at most 100", drop_pct));
+            }
+        }
+        if self.confirmations == Some(0) {
+            problems.push("confirmations must be at least 1".to_string());
+        }
+        if self.ledger_index.is_some() && self.ledger_derivation_path.is_some() {
+            problems.push("ledger_index and ledger_derivation_path are mutually exclusive".to_string());
+        }
+        if let Some(ref path) = self.ledger_derivation_path {
+            if !path.starts_with("m/") {
+                problems.push(format!("ledger_derivation_path: {:?} is not a derivation path like \"m/44'/60'/0'/0/0\"", path));
+            }
+        }
+        if let Some(ref balance) = self.min_signer_eth_balance {
+            if ethers::utils::parse_ether(balance).is_err() {
+                problems.push(format!("min_signer_eth_balance: {:?} is not an ETH amount", balance));
+            }
+        }
+
+        match problems.len() {
+            0 => Ok(()),
+            count => anyhow::bail!(
+                "{} has {} problem(s):\n  - {}",
+                self.config_path,
+                count,
+                problems.join("\n  - ")
+            ),
+        }
+    }
+
+    /// The configured signing key: COMPOUND_PRIVATE_KEY if set, otherwise
+    /// decrypted from keystore_path, otherwise the plaintext private_key
+    fn signing_key(&self) -> Result<Option<Secret>> {
+        if let Some(ref key) = self.env_private_key {
+            debug!("Using the private key from COMPOUND_PRIVATE_KEY");
+            return Ok(Some(key.clone()));
+        }
+
+        let Some(ref path) = self.keystore_path else {
+            if self.private_key.is_some() {
+                warn!("Using the plaintext private_key from config.json; consider keystore_path instead");
+            }
+            return Ok(self.private_key.clone());
+        };
+
+        let password = match self.keystore_password {
+            Some(ref password) => password.clone(),
+            None => std::env::var("KEYSTORE_PASSWORD")
+                .map(Secret::from)
+                .context("keystore_path is set but neither keystore_password nor KEYSTORE_PASSWORD is")?,
+        };
+        let wallet = LocalWallet::decrypt_keystore(path, password.expose())
+            .with_context(|| format!("Failed to decrypt keystore {}", path))?;
+        info!("Decrypted keystore {} (signer {:?})", path, wallet.address());
+
+        let key = ethers::utils::hex::encode(wallet.signer().to_bytes());
+        Ok(Some(Secret::from(format!("0x{}", key))))
+    }
+
+    /// Address of the keystore_path account, read from the keystore's `address`
+    /// field without decrypting it
+    fn keystore_address(&self) -> Result<Option<H160>> {
+        let Some(ref path) = self.keystore_path else {
+            return Ok(None);
+        };
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keystore {}", path))?;
+        let keystore: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse keystore {}", path))?;
+        let address = keystore.get("address")
+            .and_then(|address| address.as_str())
+            .with_context(|| format!("Keystore {} has no address field; set signer_address or pass --address", path))?;
+        let address = address.strip_prefix("0x").unwrap_or(address);
+        Ok(Some(address.parse().with_context(|| format!("Invalid address in keystore {}", path))?))
+    }
+
+    fn load(config_path: &str) -> Result<Self> {
+        info!("Loading configuration from {}", config_path);
+
+        let config_content = fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}. Make sure it exists, or pass --config.", config_path))?;
+
+        let mut config: Config = parse_config(config_path, &config_content)?;
+        config.config_path = config_path.to_string();
+        config.env_private_key = std::env::var("COMPOUND_PRIVATE_KEY").ok()
+            .filter(|key| !key.trim().is_empty())
+            .map(|key| Secret::from(key.trim().to_string()));
+
+        if config.markets.is_empty() {
+            if config.market_address.is_empty() {
+                anyhow::bail!("config.json must set market_address or markets");
+            }
+
+            // Single-market config: market_address may itself be an alias
+            if config.market_aliases.contains_key(&config.market_address) {
+                let alias = config.market_address.clone();
+                config.select_market(&alias)?;
+                config.selected_market = None;
+            }
+            config.markets = vec![config.single_market()];
+        } else {
+            for market in &mut config.markets {
+                if let Some(alias) = config.market_aliases.get(&market.address) {
+                    market.name.get_or_insert_with(|| alias.name.clone().unwrap_or_else(|| market.address.clone()));
+                    market.version.get_or_insert_with(|| alias.version.clone());
+                    market.address = alias.address.clone();
+                }
+                market.version.get_or_insert_with(|| config.compound_version.clone());
+            }
+        }
+
+        // Resolve each market's settings so every entry is self-contained:
+        // market value, else defaults, else the top-level field
+        let webhook_url = Some(config.webhook_url.clone()).filter(|url| !url.expose().is_empty());
+        for market in &mut config.markets {
+            market.liquidity_threshold = market.liquidity_threshold.take()
+                .or_else(|| config.defaults.liquidity_threshold.clone())
+                .or_else(|| config.liquidity_threshold.clone());
+            market.utilization_threshold_pct = market.utilization_threshold_pct
+                .or(config.defaults.utilization_threshold_pct)
+                .or(config.utilization_threshold_pct);
+            market.notification_enabled = market.notification_enabled
+                .or(config.defaults.notification_enabled)
+                .or(config.notification_enabled);
+            market.webhook_url = market.webhook_url.take()
+                .or_else(|| config.defaults.webhook_url.clone())
+                .or_else(|| webhook_url.clone());
+            market.alert_cooldown_secs = market.alert_cooldown_secs
+                .or(config.defaults.alert_cooldown_secs)
+                .or(config.alert_cooldown_secs);
+            market.poll_interval_secs = market.poll_interval_secs
+                .or(config.defaults.poll_interval_secs)
+                .or(Some(config.poll_interval_secs));
+
+            match config.threshold_mode {
+                ThresholdMode::Liquidity if market.liquidity_threshold.is_none() => {
+                    anyhow::bail!("No liquidity_threshold for market {}: set one on the market, in defaults or at the top level", market.address);
+                }
+                ThresholdMode::Utilization => match market.utilization_threshold_pct {
+                    None => anyhow::bail!(
+                        "No utilization_threshold_pct for market {}: set one on the market, in defaults or at the top level",
+                        market.address
+                    ),
+                    Some(pct) if !(pct > 0.0 && pct <= 100.0) => {
+                        anyhow::bail!("utilization_threshold_pct for market {} must be between 0 and 100, got {}", market.address, pct);
+                    }
+                    Some(_) => {}
+                },
+                ThresholdMode::Liquidity => {}
+            }
+            if market.webhook_url.is_none() && config.notification_backend == NotificationBackend::Webhook {
+                anyhow::bail!("No webhook_url for market {}: set one on the market, in defaults or at the top level", market.address);
+            }
+        }
+
+        if config.notification_backend == NotificationBackend::Telegram
+            && (config.telegram_bot_token.is_none() || config.telegram_chat_id.is_none())
+        {
+            anyhow::bail!("notification_backend \"telegram\" requires telegram_bot_token and telegram_chat_id");
+        }
+
+        if config.notification_backend == NotificationBackend::Email {
+            if config.smtp_host.is_none() || config.email_from.is_none() || config.email_to.is_empty() {
+                anyhow::bail!("notification_backend \"email\" requires smtp_host, email_from and email_to");
+            }
+            if config.smtp_username.is_some() != config.smtp_password.is_some() {
+                anyhow::bail!("smtp_username and smtp_password must be set together");
+            }
+        }
+
+        if let Some(threshold) = config.health_threshold {
+            if threshold <= 0.0 || threshold.is_nan() {
+                anyhow::bail!("health_threshold must be positive, got {}", threshold);
+            }
+        } else if !config.health_addresses.is_empty() {
+            anyhow::bail!("health_addresses requires health_threshold");
+        }
+
+        // The other commands act on a single market: default to the first
+        if config.market_address.is_empty() {
+            let first = config.markets[0].clone();
+            config.use_market(&first);
+        }
+
+        Ok(config)
+    }
+
+    /// The single-market fields as an entry of `markets`
+    fn single_market(&self) -> MarketConfig {
+        MarketConfig {
+            address: self.market_address.clone(),
+            name: self.market_name.clone(),
+            version: Some(self.compound_version.clone()),
+            liquidity_threshold: self.liquidity_threshold.clone(),
+            utilization_threshold_pct: self.utilization_threshold_pct,
+            notification_enabled: self.notification_enabled,
+            webhook_url: Some(self.webhook_url.clone()).filter(|url| !url.expose().is_empty()),
+            alert_cooldown_secs: self.alert_cooldown_secs,
+            poll_interval_secs: Some(self.poll_interval_secs),
+            price_feed_address: self.price_feed_address.clone(),
+            token_decimals: self.token_decimals,
+        }
+    }
+
+    /// Point the single-market fields at an entry of `markets`
+    fn use_market(&mut self, market: &MarketConfig) {
+        self.market_address = market.address.clone();
+        self.market_name = market.name.clone();
+        if let Some(ref version) = market.version {
+            self.compound_version = version.clone();
+        }
+        if let Some(ref threshold) = market.liquidity_threshold {
+            self.liquidity_threshold = Some(threshold.clone());
+        }
+        if market.utilization_threshold_pct.is_some() {
+            self.utilization_threshold_pct = market.utilization_threshold_pct;
+        }
+        if market.notification_enabled.is_some() {
+            self.notification_enabled = market.notification_enabled;
+        }
+        if let Some(ref url) = market.webhook_url {
+            self.webhook_url = url.clone();
+        }
+        if market.alert_cooldown_secs.is_some() {
+            self.alert_cooldown_secs = market.alert_cooldown_secs;
+        }
+        // A price feed belongs to one underlying token, so it is never inherited
+        self.price_feed_address = market.price_feed_address.clone();
+        self.token_decimals = market.token_decimals;
+    }
+
+    /// Point the config at a market given by name, alias or address, and monitor
+    /// only that market. An entry of `markets` keeps its own settings; an alias
+    /// sets the address, version and name; a plain address keeps the configured
+    /// version.
+    fn select_market(&mut self, market: &str) -> Result<()> {
+        self.selected_market = Some(market.to_string());
+
+        let configured = self.markets.iter().find(|entry| {
+            entry.name.as_deref() == Some(market) || entry.address.eq_ignore_ascii_case(market)
+        }).cloned();
+        if let Some(entry) = configured {
+            info!("Using market '{}' ({})", market, entry.address);
+            self.use_market(&entry);
+            self.markets = vec![entry];
+            return Ok(());
+        }
+
+        if let Some(alias) = self.market_aliases.get(market) {
+            info!("Using market alias '{}' ({})", market, alias.address);
+            self.market_address = alias.address.clone();
+            self.compound_version = alias.version.clone();
+            self.market_name = Some(alias.name.clone().unwrap_or_else(|| market.to_string()));
+            self.markets = vec![self.single_market()];
+            return Ok(());
+        }
+
+        if market.parse::<H160>().is_ok() {
+            self.market_address = market.to_string();
+            self.markets = vec![self.single_market()];
+            return Ok(());
+        }
+
+        let mut known: Vec<&str> = self.market_aliases.keys().map(String::as_str)
+            .chain(self.markets.iter().filter_map(|entry| entry.name.as_deref()))
+            .collect();
+        known.sort_unstable();
+        known.dedup();
+        anyhow::bail!(
+            "Unknown market '{}': not a configured market, alias or valid address. Known markets: {}",
+            market,
+            if known.is_empty() { "(none)".to_string() } else { known.join(", ") }
+        )
+    }
+}
+
+struct CompoundMonitor {
+    config: Config,
+    provider: Arc<Provider<FallbackRpc>>,
+    client: reqwest::Client,
+    /// liquidity_threshold, replaced when the config is reloaded on SIGHUP
+    thresholds: Mutex<Thresholds>,
+    alert_expression: Option<evalexpr::Node>,
+    tx_log: TxLog,
+    chain_id: tokio::sync::OnceCell<u64>,
+    started_at: Instant,
+    /// Keyed by lowercase market address; shared by the monitors of all markets
+    market_state: Arc<Mutex<HashMap<String, MarketState>>>,
+    /// Set by detect_capabilities; until then every feature is attempted
+    capabilities: tokio::sync::OnceCell<Capabilities>,
+    /// What has been learned about the contracts, shared by all markets
+    contract_cache: Arc<Mutex<ContractCache>>,
+    /// When run() last read every market, for the /health endpoint
+    last_successful_poll: Arc<Mutex<Option<Instant>>>,
+    /// Content hash of each recently sent alert and when it was sent, for
+    /// dedup_window_secs
+    sent_alert_hashes: Arc<Mutex<HashMap<u64, Instant>>>,
+    /// Index of the market the next markets_per_tick rotation starts at
+    rotation_cursor: std::sync::atomic::AtomicUsize,
+}
+
+/// Facts about contracts that never change, read once and reused by every
+/// call and market
+#[derive(Debug, Default)]
+struct ContractCache {
+    /// Which signature variant of a Comet method works on a market, once known
+    abi_variants: HashMap<(H160, &'static str), AbiVariant>,
+    /// Base token of each Comet market
+    base_tokens: HashMap<H160, H160>,
+    /// Symbol and decimals of each token
+    token_metadata: HashMap<H160, TokenMetadata>,
+}
+
+#[derive(Debug, Clone)]
+struct TokenMetadata {
+    symbol: String,
+    decimals: u8,
+}
+
+/// Whether a Comet method is read with the canonical signature or a
+/// CometCompat fallback
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AbiVariant {
+    Canonical,
+    Fallback,
+}
+
+impl CompoundMonitor {
+    async fn new(config: Config) -> Result<Self> {
+        let provider = Provider::new(FallbackRpc::connect(&config.rpc_urls).await
+            .context("Failed to create provider")?);
+        let provider = Arc::new(provider);
+
+        // Bounds every HTTP request (threshold_url, snapshot sink, notifications)
+        // so a hung endpoint can't stall the poll loop
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.webhook_timeout_secs.unwrap_or(10)))
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let chain_id = match provider.get_chainid().await {
+            Ok(chain_id) => {
+                let chain_id = chain_id.as_u64();
+                info!("Connected to chain {}", chain_id);
+                if let Some(expected) = config.expected_chain_id.filter(|expected| *expected != chain_id) {
+                    anyhow::bail!(
+                        "RPC is on chain {} but expected_chain_id is {}. Check rpc_url and the market addresses in config.json",
+                        chain_id, expected
+                    );
+                }
+                tokio::sync::OnceCell::new_with(Some(chain_id))
+            }
+            Err(e) => {
+                let e = config.redact(&e.to_string());
+                if let Some(expected) = config.expected_chain_id {
+                    anyhow::bail!("Failed to get chain id to check expected_chain_id {}: {}", expected, e);
+                }
+                warn!("Failed to get chain id: {}", e);
+                tokio::sync::OnceCell::new()
+            }
+        };
+
+        let mut config = config;
+        Self::resolve_token_decimals(&mut config, &provider).await?;
+
+        let market_state = match config.water_marks_path {
+            Some(ref path) => Self::load_water_marks(path)?,
+            None => HashMap::new(),
+        };
+
+        Self::with_shared(
+            config,
+            provider,
+            client,
+            chain_id,
+            Arc::new(Mutex::new(market_state)),
+            Arc::new(Mutex::new(ContractCache::default())),
+            Arc::new(Mutex::new(HashMap::new())),
+        )
+    }
+
+    /// Fetch the token decimals of every market whose liquidity_threshold is
+    /// written in whole tokens, so it can be converted to base units
+    async fn resolve_token_decimals(config: &mut Config, provider: &Arc<Provider<FallbackRpc>>) -> Result<()> {
+        if config.threshold_unit != ThresholdUnit::Base {
+            return Ok(());
+        }
+
+        for market in &mut config.markets {
+            if !market.liquidity_threshold.as_ref().is_some_and(LiquidityThreshold::is_decimal) {
+                continue;
+            }
+
+            let address: H160 = market.address.parse().context("Invalid market address")?;
+            let token = match market.version.as_ref().unwrap_or(&config.compound_version) {
+                CompoundVersion::V3 => Some(Comet::new(address, Arc::clone(provider)).base_token().call().await
+                    .with_context(|| format!("Failed to get base token of {}", market.address))?),
+                // cETH has no underlying token; ETH has 18 decimals
+                CompoundVersion::V2 => CToken::new(address, Arc::clone(provider)).underlying().call().await.ok(),
+            };
+            let decimals = match token {
+                Some(token) => ERC20::new(token, Arc::clone(provider)).decimals().call().await
+                    .with_context(|| format!("Failed to get token decimals for the threshold of {}", market.address))?,
+                None => 18,
+            };
+
+            info!("Threshold for {} is in whole tokens ({} decimals)", market.name.as_deref().unwrap_or(&market.address), decimals);
+            market.token_decimals = Some(decimals);
+            if market.address.eq_ignore_ascii_case(&config.market_address) {
+                config.token_decimals = Some(decimals);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A monitor for one entry of `markets`, sharing this monitor's connection
+    /// and per-market state
+    fn for_market(&self, market: &MarketConfig) -> Result<Self> {
+        let mut config = self.config.clone();
+        config.use_market(market);
+        config.markets = vec![market.clone()];
+
+        self.with_config(config)
+            .with_context(|| format!("Invalid configuration for market {}", market.address))
+    }
+
+    /// A monitor with another config, sharing this monitor's connection and
+    /// per-market state
+    fn with_config(&self, config: Config) -> Result<Self> {
+        Self::with_shared(
+            config,
+            self.provider.clone(),
+            self.client.clone(),
+            self.chain_id.clone(),
+            self.market_state.clone(),
+            self.contract_cache.clone(),
+            self.sent_alert_hashes.clone(),
+        )
+    }
+
+    fn with_shared(
+        config: Config,
+        provider: Arc<Provider<FallbackRpc>>,
+        client: reqwest::Client,
+        chain_id: tokio::sync::OnceCell<u64>,
+        market_state: Arc<Mutex<HashMap<String, MarketState>>>,
+        contract_cache: Arc<Mutex<ContractCache>>,
+        sent_alert_hashes: Arc<Mutex<HashMap<u64, Instant>>>,
+    ) -> Result<Self> {
+        let thresholds = match (&config.liquidity_threshold, config.threshold_mode) {
+            (Some(threshold), _) => Thresholds::parse(threshold, config.token_decimals)?,
+            // Only used for logs and alert_expression in utilization mode
+            (None, ThresholdMode::Utilization) => Thresholds { default: U256::zero(), schedule: Vec::new() },
+            (None, ThresholdMode::Liquidity) => anyhow::bail!("liquidity_threshold is not set"),
+        };
+
+        if config.threshold_unit == ThresholdUnit::Usd
+            && config.compound_version != CompoundVersion::V3
+            && config.price_feed_address.is_none()
+        {
+            anyhow::bail!("threshold_unit \"usd\" on a V2 market requires price_feed_address (a Chainlink USD feed)");
+        }
+
+        let alert_expression = config.alert_expression.as_deref()
+            .map(evalexpr::build_operator_tree)
+            .transpose()
+            .context("Invalid alert_expression")?;
+
+        let tx_log = TxLog::new(config.tx_log_path.as_deref().unwrap_or("tx_log.jsonl"));
+
+        Ok(Self {
+            config,
+            provider,
+            client,
+            thresholds: Mutex::new(thresholds),
+            alert_expression,
+            tx_log,
+            chain_id,
+            started_at: Instant::now(),
+            market_state,
+            capabilities: tokio::sync::OnceCell::new(),
+            contract_cache,
+            last_successful_poll: Arc::new(Mutex::new(None)),
+            sent_alert_hashes,
+            rotation_cursor: std::sync::atomic::AtomicUsize::new(0),
+        })
+    }
+
+    fn load_water_marks(path: &str) -> Result<HashMap<String, MarketState>> {
+        if !std::path::Path::new(path).exists() {
+            return Ok(HashMap::new());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path))?;
+        let persisted: HashMap<String, PersistedWaterMarks> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path))?;
+
+        persisted
+            .into_iter()
+            .map(|(market, marks)| {
+                let water_marks = WaterMarks {
+                    low: U256::from_dec_str(&marks.low_water_mark)?,
+                    high: U256::from_dec_str(&marks.high_water_mark)?,
+                };
+                let state = MarketState { water_marks: Some(water_marks), ..Default::default() };
+                Ok((market, state))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid water mark in {}", path))
+    }
+
+    /// The market as shown in logs: "name (address)", or just the address
+    fn market_label(&self) -> String {
+        match self.config.market_name {
+            Some(ref name) => format!("{} ({})", name, self.config.market_address),
+            None => self.config.market_address.clone(),
+        }
+    }
+
+    /// Render an APY percentage for logging, per rate_decimals
+    fn format_rate(&self, pct: f64) -> String {
+        format!("{:.*}%", self.config.rate_decimals.unwrap_or(2), pct)
+    }
+
+    /// Render a utilization percentage for logging, per utilization_format and
+    /// rate_decimals
+    fn format_utilization(&self, pct: f64) -> String {
+        let decimals = self.config.rate_decimals.unwrap_or(2);
+        match self.config.utilization_format {
+            UtilizationFormat::Percent => format!("{:.*}%", decimals, pct),
+            // A ratio needs two more places to show the same precision
+            UtilizationFormat::Ratio => format!("{:.*}", decimals + 2, pct / 100.0),
+            UtilizationFormat::Bps => format!("{:.*} bps", decimals.saturating_sub(2), pct * 100.0),
+        }
+    }
+
+    /// Render a signed value for logging, per log_number_format
+    fn log_signed(&self, value: I256) -> String {
+        let sign = if value.is_negative() { "-" } else { "" };
+        format!("{}{}", sign, self.log_number(value.unsigned_abs()))
+    }
+
+    /// Render a large integer for logging, per log_number_format
+    fn log_number(&self, value: U256) -> String {
+        self.config.log_number_format.format(value)
+    }
+
+    /// Update the market's low/high-water marks with a new reading, persisting
+    /// them if water_marks_path is set
+    fn record_water_marks(&self, snapshot: &MarketSnapshot) -> WaterMarks {
+        let liquidity = snapshot.available_liquidity;
+        let market = self.config.market_address.to_lowercase();
+
+        let mut states = self.market_state.lock().unwrap();
+        let state = states.entry(market).or_default();
+
+        let (water_marks, changed) = match state.water_marks {
+            None => (WaterMarks { low: liquidity, high: liquidity }, true),
+            Some(marks) if liquidity < marks.low => {
+                info!("New low-water mark for {}: {}", snapshot.symbol, self.log_number(liquidity));
+                (WaterMarks { low: liquidity, ..marks }, true)
+            }
+            Some(marks) if liquidity > marks.high => {
+                info!("New high-water mark for {}: {}", snapshot.symbol, self.log_number(liquidity));
+                (WaterMarks { high: liquidity, ..marks }, true)
+            }
+            Some(marks) => (marks, false),
+        };
+        state.water_marks = Some(water_marks);
+
+        if changed {
+            if let Some(ref path) = self.config.water_marks_path {
+                let persisted: HashMap<&String, PersistedWaterMarks> = states
+                    .iter()
+                    .filter_map(|(market, state)| {
+                        let marks = state.water_marks?;
+                        Some((market, PersistedWaterMarks {
+                            low_water_mark: marks.low.to_string(),
+                            high_water_mark: marks.high.to_string(),
+                        }))
+                    })
+                    .collect();
+
+                let result = serde_json::to_string_pretty(&persisted)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|json| fs::write(path, json).map_err(anyhow::Error::from));
+                if let Err(e) = result {
+                    warn!("Failed to persist water marks to {}: {}", path, e);
+                }
+            }
+        }
+
+        water_marks
+    }
+
+    /// The liquidity threshold in effect: the value fetched from threshold_url
+    /// if any, otherwise the first active liquidity_threshold schedule window,
+    /// otherwise the default liquidity_threshold
+    fn threshold(&self) -> U256 {
+        self.threshold_at(chrono::Utc::now())
+    }
+
+    /// The threshold in effect for threshold_mode, as reported in alerts and logs
+    fn threshold_display(&self) -> String {
+        match self.config.threshold_mode {
+            ThresholdMode::Liquidity => self.threshold().to_string(),
+            ThresholdMode::Utilization => self.config.utilization_threshold_pct.unwrap_or_default().to_string(),
+        }
+    }
+
+    fn threshold_at(&self, time: chrono::DateTime<chrono::Utc>) -> U256 {
+        let market = self.config.market_address.to_lowercase();
+        let threshold_override = self.market_state.lock().unwrap()
+            .get(&market)
+            .and_then(|state| state.threshold_override);
+
+        threshold_override.unwrap_or_else(|| self.thresholds.lock().unwrap().at(time))
+    }
+
+    /// Re-evaluate the threshold for this poll, logging any change
+    async fn refresh_threshold(&self) {
+        self.fetch_threshold().await;
+
+        let current = self.threshold();
+        let market = self.config.market_address.to_lowercase();
+        let mut states = self.market_state.lock().unwrap();
+        let state = states.entry(market).or_default();
+        if let Some(previous) = state.active_threshold {
+            if previous != current {
+                info!("Threshold changed: {} -> {}", previous, current);
+            }
+        }
+        state.active_threshold = Some(current);
+    }
+
+    /// Fetch the threshold for this market from threshold_url. The market is
+    /// looked up by address (case-insensitive) or market_name. On failure the
+    /// configured liquidity_threshold applies.
+    async fn fetch_threshold(&self) {
+        let Some(ref url) = self.config.threshold_url else {
+            return;
+        };
+
+        let fetched = async {
+            let thresholds: HashMap<String, String> = self.client
+                .get(url.expose())
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            let value = thresholds.iter()
+                .find(|(market, _)| {
+                    market.eq_ignore_ascii_case(&self.config.market_address)
+                        || self.config.market_name.as_deref() == Some(market.as_str())
+                })
+                .map(|(_, value)| value);
+
+            match value {
+                Some(value) => Ok(Some(U256::from_dec_str(value)
+                    .with_context(|| format!("Invalid threshold {:?}", value))?)),
+                None => Ok::<_, anyhow::Error>(None),
+            }
+        }.await;
+
+        let threshold = match fetched {
+            Ok(threshold) => threshold,
+            Err(e) => {
+                warn!("Failed to fetch threshold from threshold_url, using liquidity_threshold: {}",
+                    self.config.redact(&format!("{:#}", e)));
+                None
+            }
+        };
+
+        let market = self.config.market_address.to_lowercase();
+        let mut states = self.market_state.lock().unwrap();
+        states.entry(market).or_default().threshold_override = threshold;
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.get().copied().unwrap_or_default()
+    }
+
+    /// Probe the optional integrations that are enabled in the config against
+    /// the configured market, disabling any that are unavailable
+    async fn detect_capabilities(&self) -> Result<()> {
+        let mut capabilities = Capabilities::default();
+        let market: H160 = self.config.market_address.parse()
+            .context("Invalid market address")?;
+
+        // A probe failing because the RPC is down says nothing about the chain
+        if let Err(e) = self.provider.get_block_number().await {
+            warn!("Skipping capability detection, RPC unavailable: {}", self.config.redact(&e.to_string()));
+            return Ok(());
+        }
+
+        // Each probe is independent: a V2 market can have USD pricing and cap alerts
+        if self.usd_pricing_enabled() && self.has_price_source() {
+            if let Err(e) = self.base_price_usd(market).await {
+                if self.config.threshold_unit == ThresholdUnit::Usd {
+                    return Err(e).context("threshold_unit \"usd\" requires a price, which is unavailable");
+                }
+                warn!("USD pricing disabled: price unavailable ({})", self.config.redact(&format!("{:#}", e)));
+                capabilities.usd_pricing = false;
+            }
+        }
+
+        if self.config.compound_version == CompoundVersion::V2 && self.config.cap_headroom_pct.is_some() {
+            let contract = CToken::new(market, Arc::clone(&self.provider));
+            match contract.comptroller().call().await {
+                Ok(comptroller_address) => {
+                    let comptroller = Comptroller::new(comptroller_address, Arc::clone(&self.provider));
+                    if comptroller.supply_caps(market).call().await.is_err() {
+                        warn!("Supply cap alerts disabled: comptroller does not implement supplyCaps");
+                        capabilities.supply_caps = false;
+                    }
+                    if comptroller.borrow_caps(market).call().await.is_err() {
+                        warn!("Borrow cap alerts disabled: comptroller does not implement borrowCaps");
+                        capabilities.borrow_caps = false;
+                    }
+                }
+                Err(e) => {
+                    warn!("Cap alerts disabled: failed to get comptroller ({})", self.config.redact(&e.to_string()));
+                    capabilities.supply_caps = false;
+                    capabilities.borrow_caps = false;
+                }
+            }
+        }
+
+        let _ = self.capabilities.set(capabilities);
+        Ok(())
+    }
+
+    /// Chain id reported by the provider, fetched once (at startup unless that
+    /// failed)
+    async fn chain_id(&self) -> Result<u64> {
+        self.chain_id
+            .get_or_try_init(|| async {
+                let chain_id = self.provider.get_chainid().await
+                    .context("Failed to get chain id")?;
+                Ok::<_, anyhow::Error>(chain_id.as_u64())
+            })
+            .await
+            .copied()
+    }
+
+    /// Chain id to sign transactions for: chain_id from config.json (warning if
+    /// the RPC reports a different chain), otherwise the RPC's chain, otherwise
+    /// mainnet
+    async fn signing_chain_id(&self) -> u64 {
+        match (self.config.chain_id, self.chain_id().await) {
+            (Some(configured), Ok(reported)) => {
+                if configured != reported {
+                    warn!("chain_id {} in config.json differs from the RPC's chain {}; signing for {}",
+                        configured, reported, configured);
+                }
+                configured
+            }
+            (Some(configured), Err(_)) => configured,
+            (None, Ok(reported)) => {
+                info!("Signing for chain {} (reported by the RPC)", reported);
+                reported
+            }
+            (None, Err(e)) => {
+                warn!("Could not detect the chain ({}); signing for mainnet (1). Set chain_id in config.json",
+                    self.config.redact(&format!("{:#}", e)));
+                1
+            }
+        }
+    }
+
+    /// Build a block explorer link (e.g. `https://etherscan.io/tx/0x...`) for the
+    /// active chain, or None if no explorer is known for it
+    async fn explorer_link(&self, kind: &str, value: impl std::fmt::Debug) -> Option<String> {
+        let chain_id = self.chain_id().await.ok()?;
+        let base = self.config.explorer_urls.get(&chain_id)
+            .map(String::as_str)
+            .or_else(|| default_explorer_url(chain_id))?;
+
+        Some(format!("{}/{}/{:?}", base.trim_end_matches('/'), kind, value))
+    }
+
+    async fn check_liquidity(&self) -> Result<MarketSnapshot> {
+        let address: H160 = self.config.market_address.parse()
+            .context("Invalid market address")?;
+
+        match self.config.compound_version {
+            CompoundVersion::V2 => self.check_liquidity_v2(address).await,
+            CompoundVersion::V3 => {
+                self.check_liquidity_v3(address, self.config.market_name.as_deref()).await
+            }
+        }
+    }
+
+    /// Run an RPC call, retrying failures up to rpc_max_retries times with
+    /// exponential backoff from rpc_retry_base_ms
+    async fn with_rpc_retry<T, E, F, Fut>(&self, what: &str, mut call: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        let max_retries = self.config.rpc_max_retries.unwrap_or(2);
+        let mut delay = Duration::from_millis(self.config.rpc_retry_base_ms.unwrap_or(500));
+        let mut attempt = 0;
+
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < max_retries => {
+                    attempt += 1;
+                    warn!(
+                        "RPC call {} failed (attempt {}/{}): {}. Retrying in {:?}",
+                        what, attempt, max_retries + 1, self.config.redact(&e.to_string()), delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The base token of a Comet market, read once per market
+    async fn base_token_address(&self, market: H160) -> Result<H160> {
+        if let Some(&token) = self.contract_cache.lock().unwrap().base_tokens.get(&market) {
+            return Ok(token);
+        }
+
+        let contract = Comet::new(market, Arc::clone(&self.provider));
+        let token = self.with_rpc_retry("baseToken", || async { contract.base_token().call().await }).await
+            .context("Failed to get base token address (V3)")?;
+        self.contract_cache.lock().unwrap().base_tokens.insert(market, token);
+        Ok(token)
+    }
+
+    /// A token's symbol and decimals, read once per token
+    async fn token_metadata(&self, token: H160) -> Result<TokenMetadata> {
+        if let Some(metadata) = self.contract_cache.lock().unwrap().token_metadata.get(&token) {
+            return Ok(metadata.clone());
+        }
+
+        let contract = ERC20::new(token, Arc::clone(&self.provider));
+        let symbol = self.with_rpc_retry("symbol", || async { contract.symbol().call().await }).await
+            .with_context(|| format!("Failed to get symbol of token {:?}", token))?;
+        let decimals = self.with_rpc_retry("decimals", || async { contract.decimals().call().await }).await
+            .with_context(|| format!("Failed to get decimals of token {:?}", token))?;
+        let metadata = TokenMetadata { symbol, decimals };
+        self.contract_cache.lock().unwrap().token_metadata.insert(token, metadata.clone());
+        Ok(metadata)
+    }
+
+    async fn check_liquidity_v2(&self, address: H160) -> Result<MarketSnapshot> {
+        let contract = CToken::new(address, Arc::clone(&self.provider));
+
+        // Get available cash (liquidity)
+        let cash = self.with_rpc_retry("getCash", || async { contract.get_cash().call().await }).await
+            .context("Failed to get cash (V2)")?;
+
+        // Get total borrows
+        let borrows = self.with_rpc_retry("totalBorrows", || async { contract.total_borrows().call().await }).await
+            .context("Failed to get total borrows (V2)")?;
+
+        // Get total reserves
+        let reserves = self.with_rpc_retry("totalReserves", || async { contract.total_reserves().call().await }).await
+            .context("Failed to get total reserves (V2)")?;
+
+        // Get market symbol and cToken decimals
+        let TokenMetadata { symbol, decimals: ctoken_decimals } = self.token_metadata(address).await
+            .context("Failed to get cToken metadata (V2)")?;
+
+        // cETH has no underlying token; ETH has 18 decimals
+        let underlying = match self.with_rpc_retry("underlying", || async { contract.underlying().call().await }).await {
+            Ok(underlying) => self.token_metadata(underlying).await
+                .context("Failed to get underlying token metadata (V2)")?,
+            Err(_) => TokenMetadata { symbol: "ETH".to_string(), decimals: 18 },
+        };
+
+        // Underlying base units per cToken base unit, scaled by 1e18
+        let exchange_rate = self.with_rpc_retry("exchangeRateStored", || async { contract.exchange_rate_stored().call().await }).await
+            .context("Failed to get exchange rate (V2)")?;
+        let ctoken_supply = self.with_rpc_retry("totalSupply", || async { contract.total_supply().call().await }).await
+            .context("Failed to get cToken supply (V2)")?;
+        let to_underlying = |ctokens: U256| mul_div(ctokens, exchange_rate, U256::exp10(18)).unwrap_or(U256::MAX);
+        let total_supply = to_underlying(ctoken_supply);
+        let one_ctoken = to_underlying(U256::exp10(ctoken_decimals as usize));
+
+        // Utilization = borrows / (cash + borrows - reserves); undefined for an empty market
+        let utilization_pct = percentage(borrows, cash.saturating_add(borrows).saturating_sub(reserves));
+
+        info!(
+            market = %self.config.market_address,
+            symbol = %symbol,
+            available_liquidity = %self.log_number(cash),
+            total_supply = %self.log_number(total_supply),
+            total_borrows = %self.log_number(borrows),
+            total_reserves = %self.log_number(reserves),
+            utilization = %utilization_pct.map_or_else(|| "n/a".to_string(), |pct| self.format_utilization(pct)),
+            exchange_rate = %format!("1 {} = {} {}", symbol,
+                self.format_balance(one_ctoken, U256::exp10(underlying.decimals as usize)), underlying.symbol),
+            "Market reading"
+        );
+
+        // Caps are only read when cap alerting is enabled
+        let (supply_cap, borrow_cap) = if self.config.cap_headroom_pct.is_some() {
+            let comptroller_address = self.with_rpc_retry("comptroller", || async { contract.comptroller().call().await }).await
+                .context("Failed to get comptroller (V2)")?;
+            let comptroller = Comptroller::new(comptroller_address, Arc::clone(&self.provider));
+
+            // Older comptrollers don't implement supplyCaps
+            let supply_cap = if self.capabilities().supply_caps {
+                comptroller.supply_caps(address).call().await.ok()
+            } else {
+                None
+            };
+            let borrow_cap = if self.capabilities().borrow_caps {
+                comptroller.borrow_caps(address).call().await.ok()
+            } else {
+                None
+            };
+            (supply_cap, borrow_cap)
+        } else {
+            (None, None)
+        };
+
+        // V2 can only be priced with a configured Chainlink feed
+        let (base_price_usd, available_liquidity_usd) = if self.config.price_feed_address.is_some()
+            && self.usd_pricing_enabled()
+            && self.capabilities().usd_pricing
+        {
+            self.usd_values(address, cash, underlying.decimals).await?
+        } else {
+            (None, None)
+        };
+
+        Ok(MarketSnapshot {
+            symbol,
+            available_liquidity: cash,
+            total_borrows: borrows,
+            reserves: signed_reserves(reserves),
+            total_supply: Some(total_supply),
+            utilization: utilization_pct,
+            supply_apy: None,
+            borrow_apy: None,
+            base_price_usd,
+            available_liquidity_usd,
+            supply_cap,
+            borrow_cap,
+        })
+    }
+
+    fn abi_variant(&self, market: H160, method: &'static str) -> Option<AbiVariant> {
+        self.contract_cache.lock().unwrap().abi_variants.get(&(market, method)).copied()
+    }
+
+    /// Remember which variant of `method` works on `market`, logging it the
+    /// first time
+    fn set_abi_variant(&self, market: H160, method: &'static str, variant: AbiVariant, signature: &str) {
+        let previous = self.contract_cache.lock().unwrap().abi_variants.insert((market, method), variant);
+        if previous != Some(variant) {
+            match variant {
+                AbiVariant::Canonical => debug!("{:?}: reading {} via {}", market, method, signature),
+                AbiVariant::Fallback => info!("{:?}: {} not available, using fork variant {}", market, method, signature),
+            }
+        }
+    }
+
+    /// totalBorrow(), falling back to totalBorrows()
+    async fn v3_total_borrow(&self, market: H160) -> Result<U256> {
+        const METHOD: &str = "totalBorrow()";
+        let variant = self.abi_variant(market, METHOD);
+
+        if variant != Some(AbiVariant::Fallback) {
+            let contract = Comet::new(market, Arc::clone(&self.provider));
+            match contract.total_borrow().call().await {
+                Ok(total_borrow) => {
+                    self.set_abi_variant(market, METHOD, AbiVariant::Canonical, METHOD);
+                    return Ok(total_borrow);
+                }
+                Err(e) if variant == Some(AbiVariant::Canonical) => return Err(e.into()),
+                Err(e) => debug!("{} failed, trying variants: {}", METHOD, e),
+            }
+        }
+
+        let compat = CometCompat::new(market, Arc::clone(&self.provider));
+        let total_borrow = compat.total_borrows().call().await
+            .context("Neither totalBorrow() nor totalBorrows() succeeded")?;
+        self.set_abi_variant(market, METHOD, AbiVariant::Fallback, "totalBorrows()");
+        Ok(total_borrow)
+    }
+
+    /// getUtilization(), falling back to totalBorrow / totalSupply
+    async fn v3_utilization(&self, market: H160, total_supply: U256, total_borrow: U256) -> Result<U256> {
+        const METHOD: &str = "getUtilization()";
+        let variant = self.abi_variant(market, METHOD);
+
+        if variant != Some(AbiVariant::Fallback) {
+            let contract = Comet::new(market, Arc::clone(&self.provider));
+            match contract.get_utilization().call().await {
+                Ok(utilization) => {
+                    self.set_abi_variant(market, METHOD, AbiVariant::Canonical, METHOD);
+                    return Ok(utilization);
+                }
+                Err(e) if variant == Some(AbiVariant::Canonical) => return Err(e.into()),
+                Err(e) => debug!("{} failed, computing utilization: {}", METHOD, e),
+            }
+        }
+
+        self.set_abi_variant(market, METHOD, AbiVariant::Fallback, "totalBorrow() * 1e18 / totalSupply()");
+        if total_supply.is_zero() {
+            return Ok(U256::zero());
+        }
+        mul_div(total_borrow, U256::exp10(18), total_supply).context("Utilization out of range")
+    }
+
+    /// getSupplyRate(utilization) and getBorrowRate(utilization), falling back
+    /// to the argument-less getSupplyRate() and getBorrowRate()
+    async fn v3_rates(&self, market: H160, utilization: U256) -> Result<(u64, u64)> {
+        const METHOD: &str = "getSupplyRate(uint256)";
+        let variant = self.abi_variant(market, METHOD);
+
+        if variant != Some(AbiVariant::Fallback) {
+            let contract = Comet::new(market, Arc::clone(&self.provider));
+            let rates = async {
+                let supply_rate = contract.get_supply_rate(utilization).call().await?;
+                let borrow_rate = contract.get_borrow_rate(utilization).call().await?;
+                Ok::<_, ContractError<Provider<FallbackRpc>>>((supply_rate, borrow_rate))
+            }.await;
+            match rates {
+                Ok(rates) => {
+                    self.set_abi_variant(market, METHOD, AbiVariant::Canonical, "getSupplyRate(uint256)/getBorrowRate(uint256)");
+                    return Ok(rates);
+                }
+                Err(e) if variant == Some(AbiVariant::Canonical) => return Err(e.into()),
+                Err(e) => debug!("{} failed, trying variants: {}", METHOD, e),
+            }
+        }
+
+        let compat = CometCompat::new(market, Arc::clone(&self.provider));
+        let supply_rate = compat.get_supply_rate().call().await
+            .context("Neither getSupplyRate(uint256) nor getSupplyRate() succeeded")?;
+        let borrow_rate = compat.get_borrow_rate().call().await
+            .context("Neither getBorrowRate(uint256) nor getBorrowRate() succeeded")?;
+        self.set_abi_variant(market, METHOD, AbiVariant::Fallback, "getSupplyRate()/getBorrowRate()");
+        Ok((supply_rate, borrow_rate))
+    }
+
+    async fn check_liquidity_v3(&self, address: H160, market_name: Option<&str>) -> Result<MarketSnapshot> {
+        let contract = Comet::new(address, Arc::clone(&self.provider));
+
+        // Get the base token address (e.g., USDC)
+        let base_token_address = self.base_token_address(address).await?;
+
+        // Get the actual balance of base token held by the Comet contract
+        let base_token = ERC20::new(base_token_address, Arc::clone(&self.provider));
+        let contract_balance = self.with_rpc_retry("balanceOf", || async { base_token.balance_of(address).call().await }).await
+            .context("Failed to get contract balance (V3)")?;
+
+        // Get total supply (total assets supplied to the protocol)
+        let total_supply = self.with_rpc_retry("totalSupply", || async { contract.total_supply().call().await }).await
+            .context("Failed to get total supply (V3)")?;
+
+        // Get total borrows
+        let total_borrow = self.with_rpc_retry("totalBorrow", || self.v3_total_borrow(address)).await
+            .context("Failed to get total borrow (V3)")?;
+
+        // Get reserves (can be negative in V3)
+        let reserves_i256 = self.with_rpc_retry("getReserves", || async { contract.get_reserves().call().await }).await
+            .context("Failed to get reserves (V3)")?;
+
+        if reserves_i256.is_negative() {
+            warn!("Reserves are negative: {}", self.log_signed(reserves_i256));
+        }
+
+        // Available liquidity is the actual balance of base token in the contract
+        let available_liquidity = contract_balance;
+
+        // Get utilization for APY calculation
+        let utilization = self.with_rpc_retry("getUtilization", || self.v3_utilization(address, total_supply, total_borrow)).await
+            .context("Failed to get utilization (V3)")?;
+
+        // Get supply and borrow rates
+        let (supply_rate, borrow_rate) = self.with_rpc_retry("supply/borrow rates", || self.v3_rates(address, utilization)).await
+            .context("Failed to get supply/borrow rates (V3)")?;
+
+        // Calculate APY from rates
+        // Rates are per second with 18 decimals (1e18 = 100% per second)
+        let supply_apy = self.calculate_apy(supply_rate);
+        let borrow_apy = self.calculate_apy(borrow_rate);
+
+        let symbol = market_name
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| "cUSDCv3".to_string());
+
+        info!(
+            market = %self.config.market_address,
+            symbol = %symbol,
+            available_liquidity = %self.log_number(available_liquidity),
+            total_supply = %self.log_number(total_supply),
+            total_borrows = %self.log_number(total_borrow),
+            total_reserves = %self.log_signed(reserves_i256),
+            "Market reading"
+        );
+        // Convert utilization to percentage (utilization is scaled by 1e18)
+        let utilization_pct = percentage(utilization, U256::exp10(18)).unwrap_or_default();
+
+        info!(
+            market = %self.config.market_address,
+            symbol = %symbol,
+            supply_apy = %self.format_rate(supply_apy),
+            borrow_apy = %self.format_rate(borrow_apy),
+            utilization = %self.format_utilization(utilization_pct),
+            "Market rates"
+        );
+
+        // Value liquidity in USD with the configured feed or the protocol's own oracle
+        let (base_price_usd, available_liquidity_usd) = if self.usd_pricing_enabled() && self.capabilities().usd_pricing {
+            let decimals = self.token_metadata(base_token_address).await?.decimals;
+            self.usd_values(address, available_liquidity, decimals).await?
+        } else {
+            (None, None)
+        };
+
+        Ok(MarketSnapshot {
+            symbol,
+            available_liquidity,
+            total_borrows: total_borrow,
+            reserves: reserves_i256,
+            total_supply: Some(total_supply),
+            utilization: Some(utilization_pct),
+            supply_apy: Some(supply_apy),
+            borrow_apy: Some(borrow_apy),
+            base_price_usd,
+            available_liquidity_usd,
+            supply_cap: None,
+            borrow_cap: None,
+        })
+    }
+
+    fn usd_pricing_enabled(&self) -> bool {
+        self.config.usd_pricing.unwrap_or(false) || self.config.threshold_unit == ThresholdUnit::Usd
+    }
+
+    /// Whether the market can be priced: a Chainlink feed, or the Comet oracle
+    fn has_price_source(&self) -> bool {
+        self.config.price_feed_address.is_some() || self.config.compound_version == CompoundVersion::V3
+    }
+
+    /// USD price of the market's base/underlying token, from price_feed_address
+    /// if set, otherwise the Comet oracle
+    async fn base_price_usd(&self, market: H160) -> Result<f64> {
+        if let Some(ref feed) = self.config.price_feed_address {
+            let feed: H160 = feed.parse().context("Invalid price_feed_address")?;
+            let aggregator = ChainlinkAggregator::new(feed, Arc::clone(&self.provider));
+
+            let (_, answer, _, updated_at, _) = self.with_rpc_retry("latestRoundData", || async {
+                aggregator.latest_round_data().call().await
+            }).await.context("Failed to read Chainlink price feed")?;
+            let decimals = self.with_rpc_retry("decimals", || async { aggregator.decimals().call().await }).await
+                .context("Failed to read Chainlink feed decimals")?;
+
+            if answer <= I256::zero() {
+                anyhow::bail!("Chainlink feed {:?} returned a non-positive price ({})", feed, answer);
+            }
+            if updated_at.is_zero() {
+                anyhow::bail!("Chainlink feed {:?} has no completed round", feed);
+            }
+            return Ok(u256_to_f64(answer.into_raw()) / 10f64.powi(decimals as i32));
+        }
+
+        // Comet prices have 8 decimals
+        let contract = Comet::new(market, Arc::clone(&self.provider));
+        let price_feed = self.with_rpc_retry("baseTokenPriceFeed", || async { contract.base_token_price_feed().call().await }).await
+            .context("Failed to get base token price feed (V3)")?;
+        let price = self.with_rpc_retry("getPrice", || async { contract.get_price(price_feed).call().await }).await
+            .context("Failed to get base token price (V3)")?;
+        Ok(u256_to_f64(price) / 1e8)
+    }
+
+    /// Price a market's liquidity in USD when USD pricing is on, logging the
+    /// values. `decimals` is the base/underlying token's.
+    async fn usd_values(&self, market: H160, liquidity: U256, decimals: u8) -> Result<(Option<f64>, Option<f64>)> {
+        if !(self.usd_pricing_enabled() && self.has_price_source() && self.capabilities().usd_pricing) {
+            return Ok((None, None));
+        }
+
+        let price_usd = self.base_price_usd(market).await?;
+        let liquidity_usd = u256_to_f64(liquidity) / 10f64.powi(decimals as i32) * price_usd;
+
+        info!(
+            market = %self.config.market_address,
+            base_price_usd = price_usd,
+            available_liquidity_usd = liquidity_usd,
+            "Base Token Price: ${:.4} | Available Liquidity: ${:.2}", price_usd, liquidity_usd
+        );
+        Ok((Some(price_usd), Some(liquidity_usd)))
+    }
+
+    async fn send_alert(&self, alert: &LiquidityAlert) -> Result<()> {
+        match self.config.notification_backend {
+            NotificationBackend::Webhook => {
+                info!("Sending alert for market {} to webhook: {}", self.market_label(), self.config.webhook_url);
+            }
+            NotificationBackend::Telegram => {
+                info!("Sending alert for market {} to Telegram chat {}", self.market_label(),
+                    self.config.telegram_chat_id.as_deref().unwrap_or_default());
+            }
+            NotificationBackend::Email => {
+                info!("Sending alert for market {} by email to {}", self.market_label(), self.config.email_to.join(", "));
+            }
+        }
+
+        let max_retries = self.config.webhook_max_retries.unwrap_or(2);
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+
+        loop {
+            match self.deliver_alert(alert).await {
+                Ok(()) => {
+                    info!("Alert sent successfully");
+                    return Ok(());
+                }
+                Err(e) if attempt < max_retries => {
+                    attempt += 1;
+                    let jittered = self.jitter(delay);
+                    warn!(
+                        "Alert delivery failed (attempt {}/{}): {}. Retrying in {:?}",
+                        attempt, max_retries + 1, self.config.redact(&format!("{:#}", e)), jittered
+                    );
+                    tokio::time::sleep(jittered).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Randomize a retry delay by up to +/- webhook_retry_jitter_pct
+    fn jitter(&self, delay: Duration) -> Duration {
+        use rand::Rng;
+
+        let pct = self.config.webhook_retry_jitter_pct.unwrap_or(25.0).clamp(0.0, 100.0);
+        if pct == 0.0 {
+            return delay;
+        }
+
+        let factor = 1.0 + rand::thread_rng().gen_range(-pct..=pct) / 100.0;
+        delay.mul_f64(factor)
+    }
+
+    /// The alert as sent to the webhook, trimmed and ordered according to
+    /// alert_fields and alert_exclude_fields
+    fn alert_payload(&self, alert: &LiquidityAlert) -> Result<serde_json::Value> {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(alert)
+            .context("Failed to serialize alert")?
+        else {
+            unreachable!("alerts serialize to a JSON object");
+        };
+
+        if let Some(ref selected) = self.config.alert_fields {
+            fields = selected
+                .iter()
+                .filter_map(|name| fields.remove(name).map(|value| (name.clone(), value)))
+                .collect();
+        }
+        for name in &self.config.alert_exclude_fields {
+            fields.remove(name);
+        }
+
+        Ok(serde_json::Value::Object(fields))
+    }
+
+    /// Severity of an alert, from alert_severities or the built-in mapping
+    fn severity(&self, alert: &LiquidityAlert) -> Severity {
+        match alert.recovered {
+            true => Severity::Recovered,
+            false => self.config.alert_severities.get(&alert.alert_type).copied().unwrap_or_else(|| Severity::of(alert.alert_type)),
+        }
+    }
+
+    /// The alert's title prefixed with its severity emoji, if it has one
+    fn marked_title(&self, alert: &LiquidityAlert, title: String) -> String {
+        let severity = self.severity(alert);
+        let emoji = self.config.severity_styles.get(&severity)
+            .and_then(|style| style.emoji.as_deref())
+            .unwrap_or(severity.default_emoji());
+        match emoji {
+            "" => title,
+            emoji => format!("{} {}", emoji, title),
+        }
+    }
+
+    /// Color of the alert's severity; colors were checked when the config was validated
+    fn severity_color(&self, alert: &LiquidityAlert) -> u32 {
+        let severity = self.severity(alert);
+        self.config.severity_styles.get(&severity)
+            .and_then(|style| style.color.as_deref())
+            .and_then(|color| parse_color(color).ok())
+            .unwrap_or(severity.default_color())
+    }
+
+    /// The alert as a Discord message: one embed colored by severity, with the
+    /// key numbers as fields
+    fn discord_payload(&self, alert: &LiquidityAlert) -> serde_json::Value {
+        let fields: Vec<_> = alert_summary(alert)
+            .into_iter()
+            .map(|(name, value)| serde_json::json!({ "name": name, "value": value, "inline": true }))
+            .collect();
+
+        serde_json::json!({
+            "embeds": [{
+                "title": self.marked_title(alert, alert_title(alert)),
+                "description": alert.message,
+                "color": self.severity_color(alert),
+                "fields": fields,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            }]
+        })
+    }
+
+    /// The alert as a Slack Block Kit message in an attachment colored by
+    /// severity: a header marked with the severity emoji, the message, then the
+    /// key numbers as fields
+    fn slack_payload(&self, alert: &LiquidityAlert) -> serde_json::Value {
+        let title = self.marked_title(alert, alert_title(alert));
+        let fields: Vec<_> = alert_summary(alert)
+            .into_iter()
+            .map(|(name, value)| serde_json::json!({ "type": "mrkdwn", "text": format!("*{}*\n{}", name, value) }))
+            .collect();
+
+        serde_json::json!({
+            // Shown in notifications, where blocks are not rendered
+            "text": title,
+            "attachments": [{
+                "color": format!("#{:06X}", self.severity_color(alert)),
+                "blocks": [
+                    { "type": "header", "text": { "type": "plain_text", "text": title } },
+                    { "type": "section", "text": { "type": "mrkdwn", "text": alert.message } },
+                    { "type": "section", "fields": fields },
+                    { "type": "context", "elements": [{ "type": "mrkdwn", "text": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string() }] },
+                ]
+            }]
+        })
+    }
+
+    /// Make a single delivery attempt through the configured backend
+    async fn deliver_alert(&self, alert: &LiquidityAlert) -> Result<()> {
+        match self.config.notification_backend {
+            NotificationBackend::Webhook => self.deliver_webhook(alert).await,
+            NotificationBackend::Telegram => self.deliver_telegram(alert).await,
+            NotificationBackend::Email => self.deliver_email(alert).await,
+        }
+    }
+
+    /// SMTP transport for the email backend
+    fn smtp_transport(&self) -> Result<lettre::AsyncSmtpTransport<lettre::Tokio1Executor>> {
+        use lettre::{AsyncSmtpTransport, Tokio1Executor};
+
+        let host = self.config.smtp_host.as_deref().context("smtp_host is not set")?;
+        let (builder, default_port) = match self.config.smtp_security {
+            SmtpSecurity::Starttls => (AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?, 587),
+            SmtpSecurity::Tls => (AsyncSmtpTransport::<Tokio1Executor>::relay(host)?, 465),
+            SmtpSecurity::None => (AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host), 25),
+        };
+        let mut builder = builder
+            .port(self.config.smtp_port.unwrap_or(default_port))
+            .timeout(Some(self.webhook_timeout()));
+        if let (Some(username), Some(password)) = (&self.config.smtp_username, &self.config.smtp_password) {
+            builder = builder.credentials(lettre::transport::smtp::authentication::Credentials::new(
+                username.clone(),
+                password.expose().to_string(),
+            ));
+        }
+        Ok(builder.build())
+    }
+
+    /// Email the alert as plain text to every address in email_to
+    async fn deliver_email(&self, alert: &LiquidityAlert) -> Result<()> {
+        use lettre::AsyncTransport;
+
+        let from = self.config.email_from.as_deref().context("email_from is not set")?;
+        let mut builder = lettre::Message::builder()
+            .from(from.parse().with_context(|| format!("Invalid email_from: {}", from))?)
+            .subject(format!("[Compound Monitor] {}", alert_title(alert)))
+            .header(lettre::message::header::ContentType::TEXT_PLAIN);
+        for to in &self.config.email_to {
+            builder = builder.to(to.parse().with_context(|| format!("Invalid email_to address: {}", to))?);
+        }
+
+        let mut body = format!("{}\n\n", alert.message);
+        for (name, value) in alert_summary(alert) {
+            body.push_str(&format!("{}: {}\n", name, value));
+        }
+        if let Ok(time) = alert.timestamp.to_datetime() {
+            body.push_str(&format!("Time: {}\n", time.to_rfc3339()));
+        }
+
+        let message = builder.body(body).context("Failed to build alert email")?;
+        self.smtp_transport()?
+            .send(message)
+            .await
+            .context("Failed to send alert email")?;
+
+        Ok(())
+    }
+
+    /// Telegram Bot API URL for `method`
+    fn telegram_url(&self, method: &str) -> Result<String> {
+        let token = self.config.telegram_bot_token.as_ref()
+            .context("telegram_bot_token is not set")?;
+        Ok(format!("https://api.telegram.org/bot{}/{}", token.expose(), method))
+    }
+
+    /// Send the alert with the bot's sendMessage, formatted as Markdown
+    async fn deliver_telegram(&self, alert: &LiquidityAlert) -> Result<()> {
+        let chat_id = self.config.telegram_chat_id.as_deref()
+            .context("telegram_chat_id is not set")?;
+
+        let title = format!("*{}*", escape_markdown(&alert_title(alert)));
+        let mut text = format!("{}\n\n{}\n", self.marked_title(alert, title), escape_markdown(&alert.message));
+        for (name, value) in alert_summary(alert) {
+            text.push_str(&format!("\n*{}:* `{}`", name, value));
+        }
+
+        let response = self.client
+            .post(self.telegram_url("sendMessage")?)
+            .timeout(self.webhook_timeout())
+            .json(&serde_json::json!({ "chat_id": chat_id, "text": text, "parse_mode": "Markdown" }))
+            .send()
+            .await
+            .map_err(|e| match e.is_timeout() {
+                true => anyhow::anyhow!("Telegram did not respond within {}s", self.webhook_timeout().as_secs()),
+                false => anyhow::Error::new(e.without_url()).context("Failed to send Telegram request"),
+            })?;
+
+        // The Bot API answers {"ok": false, "description": ...} on errors
+        let status = response.status();
+        let body: serde_json::Value = response.json().await
+            .context("Failed to read Telegram response")?;
+        if !status.is_success() || body["ok"] != serde_json::Value::Bool(true) {
+            anyhow::bail!("Telegram returned {}: {}", status, body["description"].as_str().unwrap_or("no description"));
+        }
+
+        Ok(())
+    }
+
+    /// Make a single webhook delivery attempt. The alert only counts as delivered on a
+    /// 2xx status and, if webhook_ack is configured, a matching response body.
+    /// A `file://` webhook_url appends the alert to that file as a JSON line instead.
+    async fn deliver_webhook(&self, alert: &LiquidityAlert) -> Result<()> {
+        let payload = match self.config.webhook_type {
+            WebhookType::Generic => self.alert_payload(alert)?,
+            WebhookType::Discord => self.discord_payload(alert),
+            WebhookType::Slack => self.slack_payload(alert),
+        };
+
+        if let Some(path) = self.config.webhook_url.expose().strip_prefix("file://") {
+            return append_json_line(path, &payload);
+        }
+
+        let response = self.client
+            .post(self.config.webhook_url.expose())
+            .timeout(self.webhook_timeout())
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| match e.is_timeout() {
+                true => anyhow::anyhow!("Webhook did not respond within {}s", self.webhook_timeout().as_secs()),
+                false => anyhow::Error::new(e).context("Failed to send webhook request"),
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("Webhook returned non-success status: {}", status);
+        }
+        info!("Webhook responded with {}", status);
+
+        if let Some(ref ack) = self.config.webhook_ack {
+            let body = response.text().await
+                .context("Failed to read webhook response body")?;
+            if !ack.matches(&body) {
+                let preview: String = body.chars().take(200).collect();
+                anyhow::bail!("Webhook response did not acknowledge the alert: {}", preview);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn webhook_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.webhook_timeout_secs.unwrap_or(10))
+    }
+
+    /// The signer's native balance and the configured minimum, if the balance is
+    /// below min_signer_eth_balance
+    async fn low_signer_balance(&self, signer: H160) -> Result<Option<(U256, U256)>> {
+        let Some(ref minimum) = self.config.min_signer_eth_balance else {
+            return Ok(None);
+        };
+        let minimum = ethers::utils::parse_ether(minimum)
+            .context("Invalid min_signer_eth_balance")?;
+
+        let balance = self.provider.get_balance(signer, None).await
+            .context("Failed to get signer ETH balance")?;
+
+        Ok((balance < minimum).then_some((balance, minimum)))
+    }
+
+    /// Warn before sending transactions if the signer may not be able to pay for gas
+    async fn warn_if_low_gas(&self, signer: H160) -> Result<()> {
+        if let Some((balance, minimum)) = self.low_signer_balance(signer).await? {
+            let wei = U256::exp10(18);
+            warn!(
+                "Signer {:?} has {} ETH, below the configured minimum of {} ETH for gas",
+                signer, self.format_balance(balance, wei), self.format_balance(minimum, wei)
+            );
+        }
+        Ok(())
+    }
+
+    /// Address whose gas balance is checked each tick when monitor_signer_gas is set
+    fn monitored_signer(&self) -> Result<Option<H160>> {
+        if !self.config.monitor_signer_gas.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        if let Some(ref address) = self.config.signer_address {
+            return Ok(Some(address.parse().context("Invalid signer_address")?));
+        }
+
+        // Same precedence as signing_key: COMPOUND_PRIVATE_KEY, keystore_path, private_key
+        if self.config.env_private_key.is_none() {
+            if let Some(address) = self.config.keystore_address()? {
+                return Ok(Some(address));
+            }
+        }
+
+        match self.config.env_private_key.as_ref().or(self.config.private_key.as_ref()) {
+            Some(key) => {
+                let wallet = key.expose().parse::<LocalWallet>()
+                    .context("Invalid private key")?;
+                Ok(Some(wallet.address()))
+            }
+            None if self.config.signer == SignerKind::Ledger => {
+                anyhow::bail!("monitor_signer_gas with signer \"ledger\" requires signer_address")
+            }
+            None => anyhow::bail!("monitor_signer_gas requires signer_address, keystore_path or private_key"),
+        }
+    }
+
+    /// Refuse transactions outside the configured min/max amount bounds
+    fn validate_tx_amount(&self, amount: U256, force: bool) -> Result<()> {
+        if let Some(ref min) = self.config.min_tx_amount {
+            let min = U256::from_dec_str(min).context("Invalid min_tx_amount")?;
+            if amount < min {
+                anyhow::bail!(
+                    "Amount {} is below min_tx_amount ({}). Refusing to send transaction.",
+                    amount, min
+                );
+            }
+        }
+
+        if let Some(ref max) = self.config.max_tx_amount {
+            let max = U256::from_dec_str(max).context("Invalid max_tx_amount")?;
+            if amount > max {
+                if force {
+                    warn!("Amount {} exceeds max_tx_amount ({}), proceeding because --force was given", amount, max);
+                } else {
+                    anyhow::bail!(
+                        "Amount {} exceeds max_tx_amount ({}). Use --force to override.",
+                        amount, max
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refuse a supply or withdrawal above max_supply_amount / max_withdraw_amount,
+    /// and ask before one above confirm_above_amount unless --yes was given
+    fn check_operation_amount(&self, operation: TxOperation, amount: U256, options: &TxOptions) -> Result<()> {
+        let (field, limit, verb) = match operation {
+            TxOperation::Supply => ("max_supply_amount", &self.config.max_supply_amount, "Supply"),
+            TxOperation::Withdraw => ("max_withdraw_amount", &self.config.max_withdraw_amount, "Withdraw"),
+            TxOperation::Borrow => return Ok(()),
+        };
+
+        if let Some(ref max) = limit {
+            let max = U256::from_dec_str(max).with_context(|| format!("Invalid {}", field))?;
+            if amount > max {
+                anyhow::bail!(
+                    "Amount {} exceeds {} ({}). Refusing to send transaction; check --amount is in base units.",
+                    amount, field, max
+                );
+            }
+        }
+
+        let Some(ref threshold) = self.config.confirm_above_amount else {
+            return Ok(());
+        };
+        let threshold = U256::from_dec_str(threshold).context("Invalid confirm_above_amount")?;
+        if amount <= threshold || options.yes || options.dry_run {
+            return Ok(());
+        }
+
+        warn!("Amount {} is above confirm_above_amount ({})", amount, threshold);
+        let prompt = format!("{} {} on {}? [y/N] ", verb, amount, self.market_label());
+        if confirm(&prompt)? != 'y' {
+            anyhow::bail!("{} of {} not confirmed. Pass --yes to skip the confirmation.", verb, amount);
+        }
+        Ok(())
+    }
+
+    /// Refuse a transaction if another one was sent within min_tx_interval_secs
+    fn check_tx_throttle(&self, force: bool) -> Result<()> {
+        let Some(window) = self.config.min_tx_interval_secs else {
+            return Ok(());
+        };
+
+        let last_sent = self.tx_log.records()?
+            .into_iter()
+            .rev()
+            .find(|record| record.tx_hash.is_some());
+        let Some(last_sent) = last_sent else {
+            return Ok(());
+        };
+
+        let elapsed = chrono::Utc::now().timestamp() - last_sent.timestamp;
+        if elapsed >= window as i64 {
+            return Ok(());
+        }
+
+        if force {
+            warn!(
+                "Last transaction {:?} was {}s ago (min_tx_interval_secs: {}), proceeding because --force was given",
+                last_sent.tx_hash, elapsed, window
+            );
+            return Ok(());
+        }
+
+        anyhow::bail!(
+            "Last transaction {:?} was sent {}s ago, within min_tx_interval_secs ({}). Use --force to override.",
+            last_sent.tx_hash, elapsed, window
+        )
+    }
+
+    /// Refuse to resend an operation whose idempotency key already has a pending
+    /// or confirmed transaction in the transaction log
+    async fn check_idempotency(&self, key: &str) -> Result<()> {
+        let Some(record) = self.tx_log.latest_for_key(key)? else {
+            return Ok(());
+        };
+
+        match record.status {
+            TxStatus::Confirmed => anyhow::bail!(
+                "Operation with idempotency key '{}' was already confirmed in transaction {:?}. Refusing to resend.",
+                key, record.tx_hash
+            ),
+            TxStatus::Failed => {
+                info!("Previous attempt for idempotency key '{}' failed, retrying", key);
+                return Ok(());
+            }
+            TxStatus::Pending => {}
+        }
+
+        if let Some(tx_hash) = record.tx_hash {
+            if let Some(receipt) = self.provider.get_transaction_receipt(tx_hash).await? {
+                if receipt.status == Some(U64::one()) {
+                    self.tx_log.append(&record.with_status(TxStatus::Confirmed))?;
+                    anyhow::bail!(
+                        "Operation with idempotency key '{}' was already confirmed in transaction {:?}. Refusing to resend.",
+                        key, tx_hash
+                    );
+                }
+
+                self.tx_log.append(&record.with_status(TxStatus::Failed))?;
+                info!("Previous transaction {:?} reverted, retrying", tx_hash);
+                return Ok(());
+            }
+
+            if self.provider.get_transaction(tx_hash).await?.is_some() {
+                anyhow::bail!(
+                    "Transaction {:?} for idempotency key '{}' is still pending. Wait for it to be mined before retrying.",
+                    tx_hash, key
+                );
+            }
+        }
+
+        // Nothing known about the transaction itself; if its nonce has been used by a
+        // mined transaction we can't tell whether it was ours, so refuse to be safe
+        let mined_nonce = self.provider.get_transaction_count(record.from, None).await?;
+        if mined_nonce > record.nonce {
+            anyhow::bail!(
+                "Nonce {} recorded for idempotency key '{}' has been used by a mined transaction. Verify on a block explorer before retrying.",
+                record.nonce, key
+            );
+        }
+
+        warn!("Previous attempt for idempotency key '{}' was never mined, retrying", key);
+        self.tx_log.append(&record.with_status(TxStatus::Failed))?;
+
+        Ok(())
+    }
+
+    /// Nonce for the next transaction from `from`: `nonce` if given (--nonce),
+    /// otherwise the pending nonce, so that transactions sent back to back, or
+    /// while another is still in the mempool, don't reuse one
+    async fn next_nonce(&self, from: H160, nonce: Option<U256>) -> Result<U256> {
+        let (nonce, source) = match nonce {
+            Some(nonce) => (nonce, "given"),
+            None => {
+                let pending = self.provider
+                    .get_transaction_count(from, Some(BlockNumber::Pending.into()))
+                    .await
+                    .context("Failed to get nonce")?;
+                (pending, "pending")
+            }
+        };
+        info!("Nonce: {} ({})", nonce, source);
+        Ok(nonce)
+    }
+
+    /// Wait until a sent transaction has been mined and has the configured
+    /// number of confirmations. None if it was dropped from the mempool.
+    async fn await_receipt<P: JsonRpcClient>(&self, pending_tx: PendingTransaction<'_, P>) -> Result<Option<TransactionReceipt>> {
+        let confirmations = self.config.confirmations.unwrap_or(1);
+        let tx_hash = *pending_tx;
+        info!("Waiting for {} confirmation(s) of {:?}...", confirmations, tx_hash);
+
+        let receipt = pending_tx.confirmations(confirmations).await
+            .with_context(|| format!("Failed waiting for transaction {:?}", tx_hash))?;
+        if let Some(ref receipt) = receipt {
+            info!("Transaction {:?} mined in block {} with {} confirmation(s)",
+                tx_hash, receipt.block_number.unwrap_or_default(), confirmations);
+        }
+        Ok(receipt)
+    }
+
+    /// Broadcast a transaction, recording each state change in the transaction log
+    async fn send_recorded<M, D>(
+        &self,
+        call: ContractCall<M, D>,
+        operation: TxOperation,
+        amount: U256,
+        from: H160,
+        idempotency_key: Option<&str>,
+        nonce: Option<U256>,
+    ) -> Result<TransactionReceipt>
+    where
+        M: Middleware + 'static,
+        D: abi::Detokenize,
+    {
+        let mut call = self.with_fees(call).await?;
+        let nonce = self.next_nonce(from, nonce).await?;
+        call.tx.set_nonce(nonce);
+
+        let mut record = TxRecord {
+            timestamp: chrono::Utc::now().timestamp(),
+            operation,
+            market: self.config.market_address.clone(),
+            amount: amount.to_string(),
+            from,
+            nonce,
+            idempotency_key: idempotency_key.map(|key| key.to_string()),
+            tx_hash: None,
+            status: TxStatus::Pending,
+        };
+        self.tx_log.append(&record)?;
+
+        let pending_tx = match call.send().await {
+            Ok(pending_tx) => pending_tx,
+            Err(e) => {
+                self.tx_log.append(&record.with_status(TxStatus::Failed))?;
+
+                // Gas estimation simulates the call, so reverts surface here first
+                return Err(contract_error(e));
+            }
+        };
+
+        record.tx_hash = Some(*pending_tx);
+        self.tx_log.append(&record)?;
+        info!("Transaction sent: {:?}", *pending_tx);
+        if let Some(link) = self.explorer_link("tx", *pending_tx).await {
+            info!("Explorer: {}", link);
+        }
+
+        match self.await_receipt(pending_tx).await? {
+            Some(receipt) if receipt.status == Some(U64::one()) => {
+                self.tx_log.append(&record.with_status(TxStatus::Confirmed))?;
+                self.log_effective_gas_price(&receipt);
+                Ok(receipt)
+            }
+            Some(receipt) => {
+                self.tx_log.append(&record.with_status(TxStatus::Failed))?;
+
+                let reason = self.replay_revert_reason(&call.tx, from, &receipt).await
+                    .unwrap_or_else(|| "reason unavailable".to_string());
+                anyhow::bail!("Transaction {:?} reverted: {}", receipt.transaction_hash, reason)
+            }
+            None => {
+                self.tx_log.append(&record.with_status(TxStatus::Failed))?;
+                anyhow::bail!("Transaction {:?} was dropped", record.tx_hash)
+            }
+        }
+    }
+
+    /// Set the EIP-1559 fees of a transaction: max_fee_per_gas and
+    /// max_priority_fee_per_gas where configured, the provider's estimate for the
+    /// rest. Legacy transactions and chains without EIP-1559 are left to ethers.
+    async fn with_fees<M, D>(&self, mut call: ContractCall<M, D>) -> Result<ContractCall<M, D>>
+    where
+        M: Middleware + 'static,
+        D: abi::Detokenize,
+    {
+        let gwei = |value: f64, field: &str| -> Result<U256> {
+            if !(value.is_finite() && value >= 0.0) {
+                anyhow::bail!("Invalid {}: {}", field, value);
+            }
+            Ok(ethers::utils::parse_units(value.to_string(), "gwei")
+                .with_context(|| format!("Invalid {}: {}", field, value))?
+                .into())
+        };
+        let max_fee = self.config.max_fee_per_gas.map(|value| gwei(value, "max_fee_per_gas")).transpose()?;
+        let priority_fee = self.config.max_priority_fee_per_gas
+            .map(|value| gwei(value, "max_priority_fee_per_gas"))
+            .transpose()?;
+
+        let ethers::types::transaction::eip2718::TypedTransaction::Eip1559(ref mut tx) = call.tx else {
+            return Ok(call);
+        };
+
+        let (max_fee, priority_fee, source) = match (max_fee, priority_fee) {
+            (Some(max_fee), Some(priority_fee)) => (max_fee, priority_fee, "configured"),
+            _ => match self.provider.estimate_eip1559_fees(None).await {
+                Ok((estimated_max, estimated_priority)) => (
+                    max_fee.unwrap_or(estimated_max),
+                    priority_fee.unwrap_or(estimated_priority),
+                    if max_fee.is_some() || priority_fee.is_some() { "configured/estimated" } else { "estimated" },
+                ),
+                Err(e) if max_fee.is_none() && priority_fee.is_none() => {
+                    debug!("EIP-1559 fee estimation unavailable, leaving fees to the signer: {}", e);
+                    return Ok(call);
+                }
+                Err(e) => return Err(e).context("Failed to estimate EIP-1559 fees"),
+            },
+        };
+
+        if priority_fee > max_fee {
+            anyhow::bail!(
+                "max_priority_fee_per_gas ({} gwei) exceeds max_fee_per_gas ({} gwei)",
+                self.format_balance(priority_fee, U256::exp10(9)), self.format_balance(max_fee, U256::exp10(9))
+            );
+        }
+
+        info!(
+            "Gas fees ({}): max {} gwei, priority {} gwei",
+            source, self.format_balance(max_fee, U256::exp10(9)), self.format_balance(priority_fee, U256::exp10(9))
+        );
+        tx.max_fee_per_gas = Some(max_fee);
+        tx.max_priority_fee_per_gas = Some(priority_fee);
+        Ok(call)
+    }
+
+    /// Log the gas price a mined transaction actually paid
+    fn log_effective_gas_price(&self, receipt: &TransactionReceipt) {
+        if let Some(price) = receipt.effective_gas_price {
+            info!("Effective gas price: {} gwei", self.format_balance(price, U256::exp10(9)));
+        }
+    }
+
+    /// Simulate a transaction with eth_estimateGas and eth_call instead of
+    /// broadcasting it, logging the gas estimate and its cost at the current gas
+    /// price. Returns the call's simulated result.
+    async fn simulate<M, D>(&self, call: &ContractCall<M, D>, label: &str) -> Result<D>
+    where
+        M: Middleware + 'static,
+        D: abi::Detokenize,
+    {
+        let gas = call.estimate_gas().await
+            .map_err(contract_error)
+            .with_context(|| format!("{} simulation failed", label))?;
+        let result = call.call().await
+            .map_err(contract_error)
+            .with_context(|| format!("{} simulation failed", label))?;
+        let gas_price = self.provider.get_gas_price().await
+            .context("Failed to get gas price")?;
+
+        info!(
+            "Simulated {}: OK | Estimated gas: {} | Estimated cost: {} ETH at {} gwei",
+            label, gas, self.format_balance(gas.saturating_mul(gas_price), U256::exp10(18)),
+            self.format_balance(gas_price, U256::exp10(9))
+        );
+        Ok(result)
+    }
+
+    /// In a dry run whose approval isn't mined yet the supply itself can't be
+    /// simulated, so at least check the wallet holds the amount
+    async fn check_dry_run_funds<M: Middleware + 'static>(&self, token: &ERC20<M>, owner: H160, amount: U256) -> Result<()> {
+        let balance = token.balance_of(owner).call().await
+            .context("Failed to get token balance")?;
+        if balance < amount {
+            anyhow::bail!("Wallet balance {} is below the supply amount {}", balance, amount);
+        }
+        warn!("Supply not simulated: it needs the approval to be mined first (wallet balance {} covers the amount)", balance);
+        Ok(())
+    }
+
+    /// Re-simulate a mined, reverted transaction against the state of the block it
+    /// was included in to recover its revert reason
+    async fn replay_revert_reason(&self, tx: &ethers::types::transaction::eip2718::TypedTransaction, from: H160, receipt: &TransactionReceipt) -> Option<String> {
+        let block = receipt.block_number?;
+
+        let mut tx = tx.clone();
+        tx.set_from(from);
+
+        match self.provider.call(&tx, Some(block.into())).await {
+            Ok(_) => None,
+            Err(e) => RpcError::as_error_response(&e)?.as_revert_data().map(|data| revert::decode(&data)),
+        }
+    }
+
+    /// Provider that signs with `signer` for the configured chain
+    async fn signing_client(&self, signer: &TxSigner) -> Result<Arc<SignerMiddleware<Provider<FallbackRpc>, TxSigner>>> {
+        let signer = signer.clone().with_chain_id(self.signing_chain_id().await);
+
+        let provider = Provider::clone(&self.provider);
+        Ok(Arc::new(SignerMiddleware::new(provider, signer)))
+    }
+
+    async fn supply_v3(&self, amount: U256, signer: &TxSigner, options: &TxOptions) -> Result<Option<H256>> {
+        self.validate_tx_amount(amount, options.force)?;
+        self.check_operation_amount(TxOperation::Supply, amount, options)?;
+        self.check_tx_throttle(options.force)?;
+
+        info!("Supplying {} to Compound V3{}...", amount, if options.dry_run { " (dry run)" } else { "" });
+
+        let client = self.signing_client(signer).await?;
+        self.warn_if_low_gas(client.address()).await?;
+
+        // Checking a key can update the transaction log, which a dry run leaves alone
+        if let Some(key) = options.idempotency_key.as_deref().filter(|_| !options.dry_run) {
+            self.check_idempotency(key).await?;
+        }
+
+        let market_address: H160 = self.config.market_address.parse()?;
+        let contract = Comet::new(market_address, client.clone());
+
+        // Get base token address
+        let base_token_address = self.base_token_address(market_address).await?;
+        let base_token = ERC20::new(base_token_address, client.clone());
+
+        // Check allowance
+        let allowance = base_token.allowance(client.address(), market_address).call().await?;
+        info!("Current allowance: {}", self.format_allowance(allowance));
+
+        // An approval takes the given nonce and the supply the next one
+        let mut nonce = options.nonce;
+        if allowance < amount {
+            info!("Allowance is below the supply amount, approval required");
+            let approve_tx = base_token.approve(market_address, U256::MAX);
+            if options.dry_run {
+                self.simulate(&approve_tx, "approve").await?;
+            } else {
+                info!("Approving Compound to spend tokens...");
+                let mut approve_tx = self.with_fees(approve_tx).await?;
+                approve_tx.tx.set_nonce(self.next_nonce(client.address(), nonce).await?);
+                let pending_tx = approve_tx.send().await?;
+                let receipt = self.await_receipt(pending_tx).await?.context("Approve transaction failed")?;
+                info!("Approved! Transaction hash: {:?}", receipt.transaction_hash);
+                nonce = nonce.map(|nonce| nonce + 1);
+                self.log_effective_gas_price(&receipt);
+                if let Some(link) = self.explorer_link("tx", receipt.transaction_hash).await {
+                    info!("Explorer: {}", link);
+                }
+            }
+        } else {
+            info!("Allowance is sufficient, no approval needed");
+        }
+
+        // Supply to Compound
+        let supply_tx = match options.to {
+            Some(to) => {
+                info!("Crediting supply to {:?}", to);
+                contract.supply_to(to, base_token_address, amount)
+            }
+            None => contract.supply(base_token_address, amount),
+        };
+        if options.dry_run {
+            if allowance < amount {
+                self.check_dry_run_funds(&base_token, client.address(), amount).await?;
+            } else {
+                self.simulate(&supply_tx, "supply").await?;
+            }
+            info!("✓ Dry run complete, nothing was sent");
+            return Ok(None);
+        }
+        info!("Sending supply transaction...");
+        let receipt = self
+            .send_recorded(supply_tx, TxOperation::Supply, amount, client.address(), options.idempotency_key.as_deref(), nonce)
+            .await
+            .context("Supply transaction failed")?;
+
+        info!("✓ Supply successful!");
+        info!("Transaction hash: {:?}", receipt.transaction_hash);
+        info!("Gas used: {:?}", receipt.gas_used);
+
+        Ok(Some(receipt.transaction_hash))
+    }
+
+    /// Withdraw `amount` of the base token; U256::MAX withdraws the entire
+    /// balance, including interest accrued until the transaction is mined
+    async fn withdraw_v3(&self, amount: U256, signer: &TxSigner, options: &TxOptions) -> Result<Option<H256>> {
+        let client = self.signing_client(signer).await?;
+        let market_address: H160 = self.config.market_address.parse()?;
+        let contract = Comet::new(market_address, client.clone());
+
+        // Limits apply to the balance a full withdrawal will move
+        let withdraw_all = amount == U256::MAX;
+        let expected = if withdraw_all {
+            let balance = contract.balance_of(client.address()).call().await
+                .context("Failed to get Compound balance")?;
+            if balance.is_zero() {
+                anyhow::bail!("Nothing to withdraw: the Compound balance of {:?} is 0", client.address());
+            }
+            info!("Withdrawing the entire Compound balance (currently {})", balance);
+            balance
+        } else {
+            amount
+        };
+
+        self.validate_tx_amount(expected, options.force)?;
+        self.check_operation_amount(TxOperation::Withdraw, expected, options)?;
+        self.check_tx_throttle(options.force)?;
+
+        info!("Withdrawing {} from Compound V3{}...", expected, if options.dry_run { " (dry run)" } else { "" });
+
+        self.warn_if_low_gas(client.address()).await?;
+
+        // Checking a key can update the transaction log, which a dry run leaves alone
+        if let Some(key) = options.idempotency_key.as_deref().filter(|_| !options.dry_run) {
+            self.check_idempotency(key).await?;
+        }
+
+        // Get base token address
+        let base_token_address = self.base_token_address(market_address).await?;
+
+        // Withdraw from Compound
+        let withdraw_tx = match options.to {
+            Some(to) => {
+                info!("Sending withdrawal to {:?}", to);
+                contract.withdraw_to(to, base_token_address, amount)
+            }
+            None => contract.withdraw(base_token_address, amount),
+        };
+        if options.dry_run {
+            self.simulate(&withdraw_tx, "withdraw").await?;
+            info!("✓ Dry run complete, nothing was sent");
+            return Ok(None);
+        }
+        info!("Sending withdraw transaction...");
+        let receipt = self
+            .send_recorded(withdraw_tx, TxOperation::Withdraw, expected, client.address(), options.idempotency_key.as_deref(), options.nonce)
+            .await
+            .context("Withdraw transaction failed")?;
+
+        info!("✓ Withdraw successful!");
+        info!("Transaction hash: {:?}", receipt.transaction_hash);
+        info!("Gas used: {:?}", receipt.gas_used);
+        if withdraw_all {
+            match transferred_amount(&receipt, base_token_address, market_address) {
+                Some(withdrawn) => info!("Amount withdrawn: {}", withdrawn),
+                None => warn!("No transfer of the base token found in the receipt"),
+            }
+        }
+
+        Ok(Some(receipt.transaction_hash))
+    }
+
+    /// USD value of `account`'s debt and of what its collateral supports: the
+    /// borrow limit (borrowCollateralFactor) and the liquidation limit
+    /// (liquidateCollateralFactor), all priced by the Comet oracle
+    async fn borrow_position<M: Middleware + 'static>(&self, contract: &Comet<M>, account: H160) -> Result<BorrowPosition> {
+        // Comet prices have 8 decimals, collateral factors 18
+        let price = |price: U256| u256_to_f64(price) / 1e8;
+        let factor = |factor: u64| factor as f64 / 1e18;
+
+        let base_price = contract.get_price(contract.base_token_price_feed().call().await?).call().await
+            .context("Failed to get base token price")?;
+        let base_scale = contract.base_scale().call().await.context("Failed to get baseScale")?;
+        let debt = contract.borrow_balance_of(account).call().await.context("Failed to get borrow balance")?;
+        let debt_usd = u256_to_f64(debt) / u256_to_f64(base_scale) * price(base_price);
+
+        let mut borrow_limit_usd = 0.0;
+        let mut liquidation_limit_usd = 0.0;
+        let mut collateral = Vec::new();
+        let num_assets = contract.num_assets().call().await.context("Failed to get numAssets")?;
+        for index in 0..num_assets {
+            let (_, asset, price_feed, scale, borrow_factor, liquidate_factor, _, _) =
+                contract.get_asset_info(index).call().await
+                    .with_context(|| format!("Failed to get asset info {}", index))?;
+            let balance = contract.collateral_balance_of(account, asset).call().await
+                .with_context(|| format!("Failed to get collateral balance of {:?}", asset))?;
+            if balance == 0 {
+                continue;
+            }
+
+            let asset_price = contract.get_price(price_feed).call().await
+                .with_context(|| format!("Failed to get price of {:?}", asset))?;
+            let value_usd = balance as f64 / scale as f64 * price(asset_price);
+            borrow_limit_usd += value_usd * factor(borrow_factor);
+            liquidation_limit_usd += value_usd * factor(liquidate_factor);
+            collateral.push(CollateralPosition { asset, balance, scale, value_usd });
+        }
+
+        Ok(BorrowPosition {
+            debt_usd,
+            borrow_limit_usd,
+            liquidation_limit_usd,
+            base_price_usd: price(base_price),
+            base_scale,
+            collateral,
+        })
+    }
+
+    /// Print `account`'s collateral, debt and health ratio in the configured market
+    async fn health_check(&self, account: H160) -> Result<BorrowPosition> {
+        let market_address: H160 = self.config.market_address.parse()?;
+        let contract = Comet::new(market_address, Arc::clone(&self.provider));
+        let position = self.borrow_position(&contract, account).await?;
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Account: {:?}", account);
+        info!("Market:  {:?}", market_address);
+        info!("───────────────────────────────────────────────────");
+        if position.collateral.is_empty() {
+            info!("Collateral: none");
+        }
+        for collateral in &position.collateral {
+            let symbol = ERC20::new(collateral.asset, Arc::clone(&self.provider))
+                .symbol().call().await
+                .unwrap_or_else(|_| format!("{:?}", collateral.asset));
+            info!(
+                "Collateral: {} {} (${:.2})",
+                self.format_balance(U256::from(collateral.balance), U256::from(collateral.scale)),
+                symbol, collateral.value_usd
+            );
+        }
+        info!("Debt:              ${:.2}", position.debt_usd);
+        info!("Borrow capacity:   ${:.2}", position.borrow_limit_usd);
+        info!("Liquidation limit: ${:.2}", position.liquidation_limit_usd);
+        match position.health_ratio() {
+            Some(ratio) => {
+                info!("Health ratio:      {:.3}", ratio);
+                if ratio < 1.0 {
+                    warn!("Account can be liquidated: debt exceeds the liquidation limit");
+                } else if let Some(threshold) = self.config.health_threshold.filter(|threshold| ratio < *threshold) {
+                    warn!("Health ratio is below health_threshold ({})", threshold);
+                }
+            }
+            None => info!("Health ratio:      n/a (no debt)"),
+        }
+        info!("═══════════════════════════════════════════════════");
+
+        Ok(position)
+    }
+
+    /// Borrow the base asset from a V3 market with `withdraw(baseToken, amount)`,
+    /// after checking the account's collateral supports it
+    async fn borrow_v3(&self, amount: U256, signer: &TxSigner, options: &TxOptions) -> Result<Option<H256>> {
+        self.validate_tx_amount(amount, options.force)?;
+        self.check_tx_throttle(options.force)?;
+
+        info!("Borrowing {} from Compound V3{}...", amount, if options.dry_run { " (dry run)" } else { "" });
+
+        let client = self.signing_client(signer).await?;
+        self.warn_if_low_gas(client.address()).await?;
+
+        // Checking a key can update the transaction log, which a dry run leaves alone
+        if let Some(key) = options.idempotency_key.as_deref().filter(|_| !options.dry_run) {
+            self.check_idempotency(key).await?;
+        }
+
+        let market_address: H160 = self.config.market_address.parse()?;
+        let contract = Comet::new(market_address, client.clone());
+        let base_token_address = self.base_token_address(market_address).await?;
+
+        // A supplied balance is withdrawn first; only the rest is borrowed
+        let supplied = contract.balance_of(client.address()).call().await
+            .context("Failed to get supplied balance")?;
+        let borrowed = amount.saturating_sub(supplied);
+        if borrowed.is_zero() {
+            info!("Supplied balance ({}) covers the amount; this withdraws supplied funds and borrows nothing", supplied);
+        } else if !supplied.is_zero() {
+            info!("Supplied balance ({}) is withdrawn first; {} is borrowed", supplied, borrowed);
+        }
+
+        let position = self.borrow_position(&contract, client.address()).await?;
+        let debt_after = position.debt_usd + u256_to_f64(borrowed) / u256_to_f64(position.base_scale) * position.base_price_usd;
+        info!("───────────────────────────────────────────────────");
+        info!("Debt:              ${:.2} -> ${:.2}", position.debt_usd, debt_after);
+        info!("Borrow capacity:   ${:.2}", position.borrow_limit_usd);
+        info!("Liquidation limit: ${:.2}", position.liquidation_limit_usd);
+        info!("───────────────────────────────────────────────────");
+
+        if !borrowed.is_zero() {
+            if debt_after > position.borrow_limit_usd {
+                anyhow::bail!(
+                    "Borrowing {} would bring debt to ${:.2}, above the borrow capacity of ${:.2}. Supply more collateral first.",
+                    borrowed, debt_after, position.borrow_limit_usd
+                );
+            }
+
+            let warning_pct = self.config.borrow_warning_pct.unwrap_or(80.0);
+            let liquidation_pct = debt_after / position.liquidation_limit_usd * 100.0;
+            if liquidation_pct >= warning_pct {
+                warn!(
+                    "Debt after this borrow is {:.1}% of the liquidation limit (borrow_warning_pct: {}%); \
+                     a {:.1}% drop in collateral value would make the account liquidatable",
+                    liquidation_pct, warning_pct, 100.0 - liquidation_pct
+                );
+            } else {
+                info!("Debt after this borrow: {:.1}% of the liquidation limit", liquidation_pct);
+            }
+        }
+
+        let borrow_tx = match options.to {
+            Some(to) => {
+                info!("Sending borrowed funds to {:?}", to);
+                contract.withdraw_to(to, base_token_address, amount)
+            }
+            None => contract.withdraw(base_token_address, amount),
+        };
+        if options.dry_run {
+            self.simulate(&borrow_tx, "borrow").await?;
+            info!("✓ Dry run complete, nothing was sent");
+            return Ok(None);
+        }
+        info!("Sending borrow transaction...");
+        let receipt = self
+            .send_recorded(borrow_tx, TxOperation::Borrow, amount, client.address(), options.idempotency_key.as_deref(), options.nonce)
+            .await
+            .context("Borrow transaction failed")?;
+
+        info!("✓ Borrow successful!");
+        info!("Transaction hash: {:?}", receipt.transaction_hash);
+        info!("Gas used: {:?}", receipt.gas_used);
+
+        Ok(Some(receipt.transaction_hash))
+    }
+
+    async fn supply_v2(&self, amount: U256, signer: &TxSigner, options: &TxOptions) -> Result<Option<H256>> {
+        self.validate_tx_amount(amount, options.force)?;
+        self.check_operation_amount(TxOperation::Supply, amount, options)?;
+        self.check_tx_throttle(options.force)?;
+        if options.to.is_some() {
+            anyhow::bail!("Supplying to another address is only supported for Compound V3");
+        }
+
+        info!("Supplying {} to Compound V2{}...", amount, if options.dry_run { " (dry run)" } else { "" });
+
+        let client = self.signing_client(signer).await?;
+        self.warn_if_low_gas(client.address()).await?;
+
+        // Checking a key can update the transaction log, which a dry run leaves alone
+        if let Some(key) = options.idempotency_key.as_deref().filter(|_| !options.dry_run) {
+            self.check_idempotency(key).await?;
+        }
+
+        let market_address: H160 = self.config.market_address.parse()?;
+        let contract = CToken::new(market_address, client.clone());
+
+        // cETH takes ETH as msg.value and has no underlying token
+        let underlying_address = contract.underlying().call().await
+            .context("Failed to get underlying token (V2). cETH markets are not supported")?;
+        let underlying = ERC20::new(underlying_address, client.clone());
+
+        // Check allowance
+        let allowance = underlying.allowance(client.address(), market_address).call().await?;
+        info!("Current allowance: {}", self.format_allowance(allowance));
+
+        // An approval takes the given nonce and the supply the next one
+        let mut nonce = options.nonce;
+        if allowance < amount {
+            info!("Allowance is below the supply amount, approval required");
+            let approve_tx = underlying.approve(market_address, U256::MAX);
+            if options.dry_run {
+                self.simulate(&approve_tx, "approve").await?;
+            } else {
+                info!("Approving Compound to spend tokens...");
+                let mut approve_tx = self.with_fees(approve_tx).await?;
+                approve_tx.tx.set_nonce(self.next_nonce(client.address(), nonce).await?);
+                let pending_tx = approve_tx.send().await?;
+                let receipt = self.await_receipt(pending_tx).await?.context("Approve transaction failed")?;
+                info!("Approved! Transaction hash: {:?}", receipt.transaction_hash);
+                nonce = nonce.map(|nonce| nonce + 1);
+                self.log_effective_gas_price(&receipt);
+                if let Some(link) = self.explorer_link("tx", receipt.transaction_hash).await {
+                    info!("Explorer: {}", link);
+                }
+            }
+        } else {
+            info!("Allowance is sufficient, no approval needed");
+        }
+
+        // cTokens report most failures as a non-zero error code instead of
+        // reverting, so a failing mint would still be mined; simulate it first
+        let mint_tx = contract.mint(amount);
+        if options.dry_run {
+            if allowance < amount {
+                self.check_dry_run_funds(&underlying, client.address(), amount).await?;
+            } else {
+                let error_code = self.simulate(&mint_tx, "mint").await?;
+                if !error_code.is_zero() {
+                    anyhow::bail!("Mint would fail with Compound error code {}", error_code);
+                }
+            }
+            info!("✓ Dry run complete, nothing was sent");
+            return Ok(None);
+        }
+        let error_code = mint_tx.call().await.context("Mint simulation failed")?;
+        if !error_code.is_zero() {
+            anyhow::bail!("Mint would fail with Compound error code {}", error_code);
+        }
+
+        // Supply to Compound
+        info!("Sending mint transaction...");
+        let receipt = self
+            .send_recorded(mint_tx, TxOperation::Supply, amount, client.address(), options.idempotency_key.as_deref(), nonce)
+            .await
+            .context("Supply transaction failed")?;
+
+        info!("✓ Supply successful!");
+        info!("Transaction hash: {:?}", receipt.transaction_hash);
+        info!("Gas used: {:?}", receipt.gas_used);
+
+        Ok(Some(receipt.transaction_hash))
+    }
+
+    async fn withdraw_v2(&self, amount: U256, signer: &TxSigner, options: &TxOptions) -> Result<Option<H256>> {
+        self.validate_tx_amount(amount, options.force)?;
+        self.check_operation_amount(TxOperation::Withdraw, amount, options)?;
+        self.check_tx_throttle(options.force)?;
+        if options.to.is_some() {
+            anyhow::bail!("Withdrawing to another address is only supported for Compound V3");
+        }
+
+        info!("Withdrawing {} from Compound V2{}...", amount, if options.dry_run { " (dry run)" } else { "" });
+
+        let client = self.signing_client(signer).await?;
+        self.warn_if_low_gas(client.address()).await?;
+
+        // Checking a key can update the transaction log, which a dry run leaves alone
+        if let Some(key) = options.idempotency_key.as_deref().filter(|_| !options.dry_run) {
+            self.check_idempotency(key).await?;
+        }
+
+        let market_address: H160 = self.config.market_address.parse()?;
+        let contract = CToken::new(market_address, client.clone());
+
+        // See supply_v2: simulate so an error code doesn't get mined as success
+        let redeem_tx = contract.redeem_underlying(amount);
+        let error_code = if options.dry_run {
+            self.simulate(&redeem_tx, "redeemUnderlying").await?
+        } else {
+            redeem_tx.call().await.context("Redeem simulation failed")?
+        };
+        if !error_code.is_zero() {
+            anyhow::bail!("Redeem would fail with Compound error code {}", error_code);
+        }
+        if options.dry_run {
+            info!("✓ Dry run complete, nothing was sent");
+            return Ok(None);
+        }
+
+        // Withdraw from Compound
+        info!("Sending redeemUnderlying transaction...");
+        let receipt = self
+            .send_recorded(redeem_tx, TxOperation::Withdraw, amount, client.address(), options.idempotency_key.as_deref(), options.nonce)
+            .await
+            .context("Withdraw transaction failed")?;
+
+        info!("✓ Withdraw successful!");
+        info!("Transaction hash: {:?}", receipt.transaction_hash);
+        info!("Gas used: {:?}", receipt.gas_used);
+
+        Ok(Some(receipt.transaction_hash))
+    }
+
+    /// Execute the supply/withdraw operations in `path` in order, asking for
+    /// confirmation before each unless `yes` is set
+    async fn batch_tx(&self, path: &str, signer: &TxSigner, yes: bool, continue_on_error: bool, force: bool) -> Result<()> {
+        let operations: Vec<BatchOperation> = if path.ends_with(".csv") {
+            csv::Reader::from_path(path)
+                .with_context(|| format!("Failed to read {}", path))?
+                .deserialize()
+                .collect::<std::result::Result<_, _>>()
+                .with_context(|| format!("Failed to parse {}. Expected `op,market,amount,to` columns and optionally `idempotency_key`.", path))?
+        } else {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}. Expected a JSON array of operations.", path))?
+        };
+
+        if operations.is_empty() {
+            info!("No operations found in {}", path);
+            return Ok(());
+        }
+
+        let mut keys: Vec<&str> = operations.iter().filter_map(|operation| operation.idempotency_key.as_deref()).collect();
+        keys.sort_unstable();
+        if let Some(key) = keys.windows(2).find(|pair| pair[0] == pair[1]).map(|pair| pair[0]) {
+            anyhow::bail!("idempotency_key {:?} is used by more than one operation in {}", key, path);
+        }
+
+        // Resolved before anything is sent, so an unknown market fails the batch up front
+        let monitors = self.batch_monitors(&operations).await?;
+
+        let total = operations.len();
+        let mut results = Vec::new();
+        for (index, operation) in operations.iter().enumerate() {
+            let op = match operation.op {
+                TxOperation::Supply => "supply",
+                TxOperation::Withdraw => "withdraw",
+                TxOperation::Borrow => "borrow",
+            };
+            let market = operation.market.as_deref().unwrap_or(&self.config.market_address);
+            let summary = format!("{} {} in {}{}{}", op, operation.amount, market,
+                operation.to.as_deref().map(|to| format!(" to {}", to)).unwrap_or_default(),
+                operation.idempotency_key.as_deref().map(|key| format!(" (key {})", key)).unwrap_or_default());
+
+            info!("═══════════════════════════════════════════════════");
+            info!("[{}/{}] {}", index + 1, total, summary);
+
+            if !yes {
+                match confirm("Execute this operation? [y/N/q] ")? {
+                    'y' => {}
+                    'q' => {
+                        info!("Stopping at operation {}", index + 1);
+                        break;
+                    }
+                    _ => {
+                        info!("Skipped");
+                        results.push((summary, "skipped".to_string()));
+                        continue;
+                    }
+                }
+            }
+
+            match monitors[market].execute_batch_operation(operation, signer, force).await {
+                Ok(tx_hash) => results.push((summary, tx_hash.map_or_else(|| "simulated".to_string(), |hash| format!("{:?}", hash)))),
+                Err(e) => {
+                    let e = self.config.redact(&format!("{:#}", e));
+                    error!("Operation {} failed: {}", index + 1, e);
+                    results.push((summary, format!("FAILED: {}", e)));
+                    if !continue_on_error {
+                        break;
+                    }
+                }
+            }
+        }
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Batch summary ({} of {} operations processed):", results.len(), total);
+        for (summary, outcome) in &results {
+            info!("  {} -> {}", summary, outcome);
+        }
+
+        if results.iter().any(|(_, outcome)| outcome.starts_with("FAILED")) {
+            anyhow::bail!("Batch had failed operations");
+        }
+
+        Ok(())
+    }
+
+    /// A monitor for each market named in `operations` (the configured market
+    /// when none is given), keyed by the name as written and sharing this
+    /// monitor's connection
+    async fn batch_monitors(&self, operations: &[BatchOperation]) -> Result<HashMap<String, CompoundMonitor>> {
+        let mut names: Vec<&str> = operations.iter()
+            .map(|operation| operation.market.as_deref().unwrap_or(&self.config.market_address))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut config = self.config.clone();
+        config.markets = names.iter()
+            .map(|&name| {
+                let mut selected = self.config.clone();
+                selected.select_market(name)?;
+                Ok(selected.markets.remove(0))
+            })
+            .collect::<Result<_>>()?;
+        // Markets outside `markets` have no token decimals for a decimal threshold yet
+        Self::resolve_token_decimals(&mut config, &self.provider).await?;
+
+        names.into_iter()
+            .zip(&config.markets)
+            .map(|(name, market)| Ok((name.to_string(), self.for_market(market)?)))
+            .collect()
+    }
+
+    async fn execute_batch_operation(&self, operation: &BatchOperation, signer: &TxSigner, force: bool) -> Result<Option<H256>> {
+        let amount = U256::from_dec_str(&operation.amount)
+            .context("Invalid amount")?;
+        let to = operation.to.as_deref()
+            .map(|to| to.parse::<H160>().context("Invalid to address"))
+            .transpose()?;
+
+        // Each operation was confirmed by batch_tx, or --yes was given
+        let options = TxOptions {
+            force,
+            idempotency_key: operation.idempotency_key.clone(),
+            to,
+            dry_run: false,
+            yes: true,
+            nonce: None,
+        };
+        match (&self.config.compound_version, &operation.op) {
+            (CompoundVersion::V2, TxOperation::Supply) => self.supply_v2(amount, signer, &options).await,
+            (CompoundVersion::V2, TxOperation::Withdraw) => self.withdraw_v2(amount, signer, &options).await,
+            (CompoundVersion::V3, TxOperation::Supply) => self.supply_v3(amount, signer, &options).await,
+            (CompoundVersion::V3, TxOperation::Withdraw) => self.withdraw_v3(amount, signer, &options).await,
+            (CompoundVersion::V3, TxOperation::Borrow) => self.borrow_v3(amount, signer, &options).await,
+            (CompoundVersion::V2, TxOperation::Borrow) => anyhow::bail!("Borrow is only supported for Compound V3"),
+        }
+    }
+
+    fn format_allowance(&self, allowance: U256) -> String {
+        if allowance == U256::MAX {
+            "unlimited".to_string()
+        } else {
+            allowance.to_string()
+        }
+    }
+
+    async fn check_allowance(&self, owner: H160) -> Result<()> {
+        let market_address: H160 = self.config.market_address.parse()?;
+
+        let base_token_address = self.base_token_address(market_address).await?;
+        let base_token = ERC20::new(base_token_address, Arc::clone(&self.provider));
+
+        let TokenMetadata { symbol, decimals } = self.token_metadata(base_token_address).await?;
+        let allowance = base_token.allowance(owner, market_address).call().await?;
+
+        let formatted = if allowance == U256::MAX {
+            "unlimited".to_string()
+        } else {
+            let divisor = U256::exp10(decimals as usize);
+            format!("{} {}", self.format_balance(allowance, divisor), symbol)
+        };
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Owner:   {:?}", owner);
+        info!("Spender: {:?} (market)", market_address);
+        info!("Token:   {} (base token: {:?})", symbol, base_token_address);
+        info!("───────────────────────────────────────────────────");
+        info!("Allowance: {} ({})", formatted, self.format_allowance(allowance));
+        info!("═══════════════════════════════════════════════════");
+
+        Ok(())
+    }
+
+    /// Net contributed capital and yield for `owner` in the configured market,
+    /// from its confirmed transactions in the transaction log and its current
+    /// Compound balance
+    async fn accounting(&self, owner: H160) -> Result<()> {
+        let market_address: H160 = self.config.market_address.parse()?;
+
+        let mut supplied = U256::zero();
+        let mut withdrawn = U256::zero();
+        let mut count = 0;
+        for record in self.tx_log.latest_states()? {
+            let in_scope = record.from == owner
+                && record.status == TxStatus::Confirmed
+                && record.market.parse::<H160>().is_ok_and(|market| market == market_address);
+            if !in_scope {
+                continue;
+            }
+
+            let amount = U256::from_dec_str(&record.amount)
+                .with_context(|| format!("Invalid amount {:?} in transaction log", record.amount))?;
+            match record.operation {
+                TxOperation::Supply => supplied = supplied.saturating_add(amount),
+                // Borrowed funds leave the market just like a withdrawal
+                TxOperation::Withdraw | TxOperation::Borrow => withdrawn = withdrawn.saturating_add(amount),
+            }
+            count += 1;
+        }
+
+        let reading = self.read_balance(owner, market_address).await?;
+        let divisor = U256::exp10(reading.decimals as usize);
+        let symbol = &reading.symbol;
+
+        // a - b with a sign, since contributions and yield can be negative
+        let signed = |a: U256, b: U256| {
+            if a >= b {
+                format!("{} {}", self.format_balance(a - b, divisor), symbol)
+            } else {
+                format!("-{} {}", self.format_balance(b - a, divisor), symbol)
+            }
+        };
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Address: {:?}", owner);
+        info!("Market:  {:?}", market_address);
+        info!("Confirmed transactions: {} (from {})", count, self.tx_log.path().display());
+        info!("───────────────────────────────────────────────────");
+        info!("Supplied:          {} {}", self.format_balance(supplied, divisor), symbol);
+        info!("Withdrawn:         {} {}", self.format_balance(withdrawn, divisor), symbol);
+        info!("Net contributed:   {}", signed(supplied, withdrawn));
+        info!("Compound balance:  {} {}", self.format_balance(reading.compound_balance, divisor), symbol);
+        info!("───────────────────────────────────────────────────");
+        // Yield = what you have now plus what you took out, minus what you put in
+        info!("Total yield:       {}", signed(reading.compound_balance.saturating_add(withdrawn), supplied));
+        info!("═══════════════════════════════════════════════════");
+
+        Ok(())
+    }
+
+    /// Replay the configured market's recorded snapshots through alert_condition,
+    /// using the threshold that was in effect at each snapshot's time
+    fn backtest(&self, input: &str) -> Result<()> {
+        let content = fs::read_to_string(input)
+            .with_context(|| format!("Failed to read {}", input))?;
+
+        // Lines are either single snapshots or whole sink payloads ({"markets": [...]})
+        let mut entries: Vec<SnapshotEntry> = Vec::new();
+        for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let value: serde_json::Value = serde_json::from_str(line)
+                .with_context(|| format!("Invalid JSON on line {} of {}", index + 1, input))?;
+            let parsed = match value.get("markets") {
+                Some(markets) => serde_json::from_value(markets.clone()),
+                None => serde_json::from_value(value).map(|entry| vec![entry]),
+            };
+            entries.extend(parsed.with_context(|| format!("Invalid snapshot on line {} of {}", index + 1, input))?);
+        }
+
+        let entries: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| entry.market_address.eq_ignore_ascii_case(&self.config.market_address))
+            .collect();
+        if entries.is_empty() {
+            info!("No snapshots for market {} in {}", self.config.market_address, input);
+            return Ok(());
+        }
+
+        info!("Replaying {} snapshots for market {}...", entries.len(), self.config.market_address);
+        info!("═══════════════════════════════════════════════════");
+
+        let cooldown = chrono::Duration::seconds(self.config.alert_cooldown_secs.unwrap_or(0) as i64);
+        let mut alerting = 0;
+        let mut episodes = 0;
+        let mut sent = 0;
+        let mut last_sent = None;
+        let mut in_breach = false;
+        for entry in &entries {
+            let time = entry.timestamp.to_datetime()?;
+            let snapshot = entry.to_snapshot()?;
+
+            match self.alert_condition(&snapshot, self.threshold_at(time)) {
+                Some(message) => {
+                    alerting += 1;
+                    if last_sent.is_none_or(|sent_at| time - sent_at >= cooldown) {
+                        sent += 1;
+                        last_sent = Some(time);
+                    }
+                    if !in_breach {
+                        episodes += 1;
+                        info!("{} ALERT  {}", time.to_rfc3339(), message);
+                    }
+                    in_breach = true;
+                }
+                None => {
+                    if in_breach {
+                        info!("{} CLEAR  Available liquidity: {}", time.to_rfc3339(), self.log_number(snapshot.available_liquidity));
+                    }
+                    in_breach = false;
+                    // Recovery resets the cooldown, as in the monitor
+                    last_sent = None;
+                }
+            }
+        }
+
+        let first = entries[0].timestamp.to_datetime()?;
+        let last = entries[entries.len() - 1].timestamp.to_datetime()?;
+        info!("═══════════════════════════════════════════════════");
+        info!("Period: {} to {}", first.to_rfc3339(), last.to_rfc3339());
+        info!("Snapshots: {} | Alerting polls: {} | Breach episodes: {}", entries.len(), alerting, episodes);
+        info!("Without deduplication, {} alerts would have been sent", alerting);
+        if !cooldown.is_zero() {
+            info!("With alert_cooldown_secs = {}, {} alerts would have been sent", cooldown.num_seconds(), sent);
+        }
+
+        Ok(())
+    }
+
+    /// Preflight the email backend: connect to (and log in to) the SMTP server
+    async fn test_smtp(&self) -> Result<()> {
+        info!("═══════════════════════════════════════════════════");
+        info!("SMTP server: {} ({:?})", self.config.smtp_host.as_deref().unwrap_or_default(), self.config.smtp_security);
+        info!("Recipients: {}", self.config.email_to.join(", "));
+
+        let started = Instant::now();
+        let result = match self.smtp_transport() {
+            Ok(transport) => transport.test_connection().await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(true) => {
+                info!("SMTP connection: ok in {:?}", started.elapsed());
+                info!("═══════════════════════════════════════════════════");
+                Ok(())
+            }
+            Ok(false) => {
+                info!("SMTP connection: FAILED (server did not answer NOOP)");
+                info!("═══════════════════════════════════════════════════");
+                anyhow::bail!("SMTP server check failed")
+            }
+            Err(e) => {
+                info!("SMTP connection: FAILED ({})", self.config.redact(&format!("{:#}", e)));
+                info!("═══════════════════════════════════════════════════");
+                Err(e).context("SMTP server check failed")
+            }
+        }
+    }
+
+    /// Preflight the telegram backend: check the bot token with getMe
+    async fn test_telegram(&self) -> Result<()> {
+        info!("═══════════════════════════════════════════════════");
+        info!("Telegram chat: {}", self.config.telegram_chat_id.as_deref().unwrap_or_default());
+
+        let started = Instant::now();
+        let result = async {
+            let response = self.client
+                .get(self.telegram_url("getMe")?)
+                .timeout(self.webhook_timeout())
+                .send()
+                .await
+                .map_err(|e| e.without_url())?;
+            let body: serde_json::Value = response.json().await?;
+            match body["result"]["username"].as_str() {
+                Some(username) if body["ok"] == serde_json::Value::Bool(true) => Ok(username.to_string()),
+                _ => anyhow::bail!("{}", body["description"].as_str().unwrap_or("unexpected getMe response")),
+            }
+        }
+        .await;
+
+        match result {
+            Ok(username) => {
+                info!("Bot: @{} (getMe ok in {:?})", username, started.elapsed());
+                info!("═══════════════════════════════════════════════════");
+                Ok(())
+            }
+            Err(e) => {
+                info!("Bot: FAILED ({})", self.config.redact(&format!("{:#}", e)));
+                info!("═══════════════════════════════════════════════════");
+                Err(e).context("Telegram bot token check failed")
+            }
+        }
+    }
+
+    /// Send a made-up liquidity alert for the configured market, bypassing
+    /// notification_enabled, the grace period, cooldown and deduplication
+    async fn test_alert(&self) -> Result<()> {
+        let threshold = Some(self.threshold()).filter(|threshold| !threshold.is_zero())
+            .unwrap_or_else(|| U256::exp10(12));
+        let available_liquidity = threshold / 2;
+        let snapshot = MarketSnapshot {
+            symbol: "TEST".to_string(),
+            available_liquidity,
+            total_borrows: threshold * 4,
+            reserves: signed_reserves(threshold / 10),
+            total_supply: None,
+            utilization: Some(80.0),
+            supply_apy: None,
+            borrow_apy: None,
+            base_price_usd: None,
+            available_liquidity_usd: None,
+            supply_cap: None,
+            borrow_cap: None,
+        };
+        let message = format!(
+            "Test alert: available liquidity ({}) is below threshold ({}). Sent by test-alert; no threshold was actually breached.",
+            available_liquidity, self.threshold_display()
+        );
+        let alert = self.build_alert(&snapshot, AlertType::Liquidity, message);
+
+        info!("═══════════════════════════════════════════════════");
+        match self.config.notification_backend {
+            NotificationBackend::Webhook => info!("Sending a test alert ({} webhook format)",
+                serde_json::to_value(self.config.webhook_type)?.as_str().unwrap_or_default()),
+            _ => info!("Sending a test alert"),
+        }
+        info!("═══════════════════════════════════════════════════");
+        self.send_alert(&alert).await
+            .map_err(|e| anyhow::anyhow!(self.config.redact(&format!("{:#}", e))))
+            .context("Test alert was not delivered")?;
+        info!("✓ Test alert delivered");
+        Ok(())
+    }
+
+    /// Preflight the notification path: resolve, connect to and request the
+    /// webhook URL without sending an alert
+    async fn test_webhook(&self) -> Result<()> {
+        match self.config.notification_backend {
+            NotificationBackend::Webhook => {}
+            NotificationBackend::Telegram => return self.test_telegram().await,
+            NotificationBackend::Email => return self.test_smtp().await,
+        }
+
+        let webhook_url = self.config.webhook_url.expose();
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Webhook: {}", self.config.webhook_url);
+
+        if let Some(path) = webhook_url.strip_prefix("file://") {
+            let result = fs::OpenOptions::new().create(true).append(true).open(path);
+            info!("File sink: {}", match result {
+                Ok(_) => "writable".to_string(),
+                Err(ref e) => format!("FAILED ({})", e),
+            });
+            info!("═══════════════════════════════════════════════════");
+            return result.map(|_| ()).with_context(|| format!("Cannot append to {}", path));
+        }
+
+        let url = reqwest::Url::parse(webhook_url).context("Invalid webhook_url")?;
+        let host = url.host_str().context("webhook_url has no host")?;
+        let port = url.port_or_known_default().context("webhook_url has no port")?;
+
+        let started = Instant::now();
+        let addrs: Vec<_> = match tokio::net::lookup_host((host, port)).await {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => {
+                info!("DNS resolution: FAILED ({})", e);
+                info!("═══════════════════════════════════════════════════");
+                return Err(e).context("Failed to resolve webhook host");
+            }
+        };
+        let addr_list: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        info!("DNS resolution: ok in {:?} ({})", started.elapsed(), addr_list.join(", "));
+
+        let started = Instant::now();
+        let tcp = match addrs.first() {
+            Some(addr) => tokio::net::TcpStream::connect(addr).await,
+            None => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses")),
+        };
+        if let Err(e) = tcp {
+            info!("TCP connect: FAILED ({})", e);
+            info!("═══════════════════════════════════════════════════");
+            return Err(e).context("Failed to connect to webhook host");
+        }
+        info!("TCP connect: ok in {:?}", started.elapsed());
+
+        let https = url.scheme() == "https";
+        let started = Instant::now();
+        let result = self.client.head(url).timeout(self.webhook_timeout()).send().await;
+        let elapsed = started.elapsed();
+
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                // TCP already connected, so a connect error here is the TLS handshake
+                if https && e.is_connect() {
+                    info!("TLS handshake: FAILED");
+                }
+                let e = anyhow::Error::from(e.without_url());
+                info!("HTTP request: FAILED ({})", self.config.redact(&format!("{:#}", e)));
+                info!("═══════════════════════════════════════════════════");
+                return Err(e).context("Webhook request failed");
+            }
+        };
+
+        if https {
+            info!("TLS handshake: ok");
+        }
+        info!("HTTP status: {} (HEAD, round trip {:?})", response.status(), elapsed);
+        info!("═══════════════════════════════════════════════════");
+
+        // Receivers that only accept POST may answer HEAD with 405, which
+        // still proves the endpoint is reachable
+        if response.status().is_server_error() {
+            anyhow::bail!("Webhook returned server error: {}", response.status());
+        }
+
+        Ok(())
+    }
+
+    async fn read_balance(&self, address: H160, market_address: H160) -> Result<BalanceReading> {
+        let contract = Comet::new(market_address, Arc::clone(&self.provider));
+
+        // Get base token address
+        let base_token_address = self.base_token_address(market_address).await?;
+        let base_token = ERC20::new(base_token_address, Arc::clone(&self.provider));
+
+        // Get token info
+        let TokenMetadata { symbol, decimals } = self.token_metadata(base_token_address).await?;
+
+        // Check wallet balance
+        let wallet_balance = base_token.balance_of(address).call().await?;
+
+        // Check Compound balance
+        let compound_balance = contract.balance_of(address).call().await?;
+
+        // A failed rewards read shouldn't hide the balances
+        let rewards = match self.read_rewards(address, market_address).await {
+            Ok(rewards) => rewards,
+            Err(e) => {
+                warn!("Failed to read rewards owed to {:?}: {:#}", address, e);
+                None
+            }
+        };
+
+        Ok(BalanceReading {
+            base_token: base_token_address,
+            symbol,
+            decimals,
+            wallet_balance,
+            compound_balance,
+            rewards,
+        })
+    }
+
+    /// Rewards `address` can claim for `market_address` from the CometRewards
+    /// contract at rewards_address, if configured
+    async fn read_rewards(&self, address: H160, market_address: H160) -> Result<Option<RewardReading>> {
+        let Some(ref rewards_address) = self.config.rewards_address else {
+            return Ok(None);
+        };
+        let rewards_address: H160 = rewards_address.parse().context("Invalid rewards_address")?;
+        let rewards = CometRewards::new(rewards_address, Arc::clone(&self.provider));
+
+        let (token, owed) = rewards.get_reward_owed(market_address, address).call().await
+            .context("getRewardOwed failed")?;
+        // A market without a reward configured reports the zero address
+        if token.is_zero() {
+            return Ok(None);
+        }
+
+        let TokenMetadata { symbol, decimals } = self.token_metadata(token).await
+            .context("Failed to get reward token metadata")?;
+
+        Ok(Some(RewardReading { token, symbol, decimals, owed }))
+    }
+
+    /// Read and print `address`'s balance, as a JSON object on stdout with `json`
+    async fn check_balance(&self, address: &str, name: Option<&str>, json: bool) -> Result<BalanceReading> {
+        let address: H160 = address.parse().context("Invalid address")?;
+        let market_address: H160 = self.config.market_address.parse()?;
+
+        let reading = self.read_balance(address, market_address).await?;
+        if json {
+            print_json(&self.balance_json(address, name, &reading))?;
+        } else {
+            self.print_balance(address, name, reading.clone()).await;
+        }
+
+        Ok(reading)
+    }
+
+    /// A balance reading for --json output, with raw and formatted amounts
+    fn balance_json(&self, address: H160, name: Option<&str>, reading: &BalanceReading) -> serde_json::Value {
+        let divisor = U256::exp10(reading.decimals as usize);
+        let rewards = reading.rewards.as_ref().map(|rewards| {
+            let divisor = U256::exp10(rewards.decimals as usize);
+            serde_json::json!({
+                "token": format!("{:?}", rewards.token),
+                "symbol": rewards.symbol,
+                "decimals": rewards.decimals,
+                "owed": rewards.owed.to_string(),
+                "owed_formatted": self.format_balance(rewards.owed, divisor),
+            })
+        });
+
+        serde_json::json!({
+            "address": format!("{:?}", address),
+            "name": name,
+            "market": self.config.market_address,
+            "base_token": format!("{:?}", reading.base_token),
+            "symbol": reading.symbol,
+            "decimals": reading.decimals,
+            "wallet_balance": reading.wallet_balance.to_string(),
+            "wallet_balance_formatted": self.format_balance(reading.wallet_balance, divisor),
+            "compound_balance": reading.compound_balance.to_string(),
+            "compound_balance_formatted": self.format_balance(reading.compound_balance, divisor),
+            "rewards": rewards,
+        })
+    }
+
+    async fn print_balance(&self, address: H160, name: Option<&str>, reading: BalanceReading) {
+        let BalanceReading {
+            base_token: base_token_address,
+            symbol,
+            decimals,
+            wallet_balance,
+            compound_balance,
+            rewards,
+        } = reading;
+
+        // Format balances for display
+        let divisor = U256::exp10(decimals as usize);
+        let wallet_formatted = self.format_balance(wallet_balance, divisor);
+        let compound_formatted = self.format_balance(compound_balance, divisor);
+
+        info!("═══════════════════════════════════════════════════");
+        if let Some(name) = name {
+            info!("Name: {}", name);
+        }
+        info!("Address: {:?}", address);
+        if let Some(link) = self.explorer_link("address", address).await {
+            info!("Explorer: {}", link);
+        }
+        info!("Token: {} (base token: {})", symbol, base_token_address);
+        info!("Decimals: {}", decimals);
+        info!("───────────────────────────────────────────────────");
+        info!("Wallet balance:   {} {} ({})", wallet_formatted, symbol, wallet_balance);
+        info!("Compound balance: {} {} ({})", compound_formatted, symbol, compound_balance);
+        if let Some(rewards) = rewards {
+            let divisor = U256::exp10(rewards.decimals as usize);
+            info!("Rewards owed:     {} {} ({}, token {:?})",
+                self.format_balance(rewards.owed, divisor), rewards.symbol, rewards.owed, rewards.token);
+        }
+        info!("═══════════════════════════════════════════════════");
+    }
+
+    /// Returns the number of addresses checked and how many of them failed. With
+    /// `json` the balances are printed as a JSON array on stdout, failed
+    /// addresses included with an `error` field. With `summary` the totals of
+    /// the successful readings follow; as JSON the output is then an object
+    /// with `addresses` and `summary`.
+    async fn check_balance_batch(&self, json: bool, summary: bool) -> Result<(usize, usize)> {
+        let address_config = MonitorAddressConfig::load(&self.config.monitor_addresses_path)?;
+
+        if address_config.addresses.is_empty() {
+            info!("No addresses found in {}", self.config.monitor_addresses_path);
+            if json {
+                print_json(&serde_json::json!([]))?;
+            }
+            return Ok((0, 0));
+        }
 
         let market_address: H160 = self.config.market_address.parse()?;
-        let contract = Comet::new(market_address, client);
+        let concurrency = self.config.max_concurrent_requests.unwrap_or(10).max(1);
+        let timeout = Duration::from_secs(self.config.balance_timeout_secs.unwrap_or(30));
+
+        info!("Checking balances for {} addresses ({} concurrent, {}s timeout each)...",
+            address_config.addresses.len(), concurrency, timeout.as_secs());
+        info!("");
+
+        let started = Instant::now();
+        // Read concurrently, but print in the order of monitor_address.json
+        let readings: Vec<_> = stream::iter(&address_config.addresses)
+            .map(|monitor_addr| async move {
+                let address: H160 = match monitor_addr.address.parse() {
+                    Ok(address) => address,
+                    Err(e) => return (monitor_addr, Err(anyhow::anyhow!("Invalid address: {}", e))),
+                };
+                let reading = match tokio::time::timeout(timeout, self.read_balance(address, market_address)).await {
+                    Ok(reading) => reading.map(|reading| (address, reading)),
+                    Err(_) => Err(anyhow::anyhow!("Timed out after {}s", timeout.as_secs())),
+                };
+                (monitor_addr, reading)
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        let mut failed = 0;
+        let mut entries = Vec::new();
+        let mut totals: Option<BalanceTotals> = None;
+        for (monitor_addr, reading) in readings {
+            if let Ok((_, ref reading)) = reading {
+                totals.get_or_insert_with(|| BalanceTotals::new(reading)).add(reading);
+            }
+            match reading {
+                Ok((address, reading)) if json => {
+                    entries.push(self.balance_json(address, Some(&monitor_addr.name), &reading));
+                }
+                Ok((address, reading)) => {
+                    self.print_balance(address, Some(&monitor_addr.name), reading).await;
+                    info!("");
+                }
+                Err(e) => {
+                    failed += 1;
+                    let e = self.config.redact(&format!("{:#}", e));
+                    error!("Failed to check balance for {} ({}): {}", monitor_addr.name, monitor_addr.address, e);
+                    if json {
+                        entries.push(serde_json::json!({
+                            "address": monitor_addr.address,
+                            "name": monitor_addr.name,
+                            "error": e,
+                        }));
+                    } else {
+                        info!("");
+                    }
+                }
+            }
+        }
+        let entries = serde_json::Value::Array(entries);
+        match (json, summary) {
+            (true, true) => print_json(&serde_json::json!({
+                "addresses": entries,
+                "summary": totals.as_ref().map(|totals| self.balance_totals_json(totals)),
+            }))?,
+            (true, false) => print_json(&entries)?,
+            (false, true) => self.print_balance_totals(totals.as_ref()),
+            (false, false) => {}
+        }
+
+        info!("Checked {} addresses in {:.1?}: {} ok, {} failed",
+            address_config.addresses.len(),
+            started.elapsed(),
+            address_config.addresses.len() - failed,
+            failed);
+
+        Ok((address_config.addresses.len(), failed))
+    }
+
+    fn print_balance_totals(&self, totals: Option<&BalanceTotals>) {
+        let Some(totals) = totals else {
+            info!("No balances read, no totals to show");
+            return;
+        };
+        let divisor = U256::exp10(totals.decimals as usize);
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Total across {} addresses ({})", totals.addresses, totals.symbol);
+        info!("───────────────────────────────────────────────────");
+        info!("Wallet balance:   {} {} ({})", self.format_balance(totals.wallet_balance, divisor), totals.symbol, totals.wallet_balance);
+        info!("Compound balance: {} {} ({})", self.format_balance(totals.compound_balance, divisor), totals.symbol, totals.compound_balance);
+        info!("═══════════════════════════════════════════════════");
+    }
+
+    fn balance_totals_json(&self, totals: &BalanceTotals) -> serde_json::Value {
+        let divisor = U256::exp10(totals.decimals as usize);
+        serde_json::json!({
+            "addresses": totals.addresses,
+            "symbol": totals.symbol,
+            "decimals": totals.decimals,
+            "wallet_balance": totals.wallet_balance.to_string(),
+            "wallet_balance_formatted": self.format_balance(totals.wallet_balance, divisor),
+            "compound_balance": totals.compound_balance.to_string(),
+            "compound_balance_formatted": self.format_balance(totals.compound_balance, divisor),
+        })
+    }
+
+    /// Batch balance across every configured V3 market, grouped by base token so
+    /// markets sharing an asset are shown together with per-token subtotals
+    async fn check_balance_all_markets(&self) -> Result<()> {
+        let address_config = MonitorAddressConfig::load(&self.config.monitor_addresses_path)?;
+
+        if address_config.addresses.is_empty() {
+            info!("No addresses found in {}", self.config.monitor_addresses_path);
+            return Ok(());
+        }
+
+        let mut markets: Vec<(String, String)> = Vec::new();
+        if self.config.compound_version == CompoundVersion::V3 {
+            let name = self.config.market_name.clone().unwrap_or_else(|| "configured market".to_string());
+            markets.push((name, self.config.market_address.clone()));
+        }
+        let entries = self.config.markets.iter()
+            .filter(|entry| entry.version.as_ref().unwrap_or(&self.config.compound_version) == &CompoundVersion::V3);
+        for entry in entries {
+            if !markets.iter().any(|(_, address)| address.eq_ignore_ascii_case(&entry.address)) {
+                markets.push((entry.name.clone().unwrap_or_else(|| entry.address.clone()), entry.address.clone()));
+            }
+        }
+        let mut aliases: Vec<_> = self.config.market_aliases.iter()
+            .filter(|(_, alias)| alias.version == CompoundVersion::V3)
+            .collect();
+        aliases.sort_unstable_by_key(|(key, _)| key.as_str());
+        for (key, alias) in aliases {
+            if !markets.iter().any(|(_, address)| address.eq_ignore_ascii_case(&alias.address)) {
+                markets.push((alias.name.clone().unwrap_or_else(|| key.clone()), alias.address.clone()));
+            }
+        }
+
+        if markets.is_empty() {
+            info!("No V3 markets configured");
+            return Ok(());
+        }
+
+        info!("Checking balances for {} addresses across {} markets...",
+            address_config.addresses.len(), markets.len());
+        info!("");
+
+        // (base token, market name, market address, address name, reading), in market order
+        let mut readings = Vec::new();
+        for (market_name, market_address) in &markets {
+            let market: H160 = match market_address.parse() {
+                Ok(market) => market,
+                Err(e) => {
+                    error!("Invalid market address for {} ({}): {}", market_name, market_address, e);
+                    continue;
+                }
+            };
+
+            for monitor_addr in &address_config.addresses {
+                let reading = match monitor_addr.address.parse::<H160>() {
+                    Ok(address) => self.read_balance(address, market).await,
+                    Err(e) => Err(anyhow::anyhow!("Invalid address: {}", e)),
+                };
+                match reading {
+                    Ok(reading) => readings.push((market_name, market, monitor_addr, reading)),
+                    Err(e) => error!("Failed to check balance for {} ({}) in {}: {}",
+                        monitor_addr.name, monitor_addr.address, market_name, self.config.redact(&format!("{:#}", e))),
+                }
+            }
+        }
+
+        let mut tokens: Vec<H160> = Vec::new();
+        for (_, _, _, reading) in &readings {
+            if !tokens.contains(&reading.base_token) {
+                tokens.push(reading.base_token);
+            }
+        }
+
+        for token in tokens {
+            let group: Vec<_> = readings.iter().filter(|(_, _, _, r)| r.base_token == token).collect();
+            let (_, _, _, first) = group[0];
+            let divisor = U256::exp10(first.decimals as usize);
+
+            info!("═══════════════════════════════════════════════════");
+            info!("Token: {} (base token: {:?})", first.symbol, token);
+            info!("Decimals: {}", first.decimals);
+
+            let mut current_market = None;
+            let mut compound_total = U256::zero();
+            for (market_name, market, monitor_addr, reading) in &group {
+                if current_market != Some(*market) {
+                    info!("───────────────────────────────────────────────────");
+                    info!("Market: {} ({:?})", market_name, market);
+                    current_market = Some(*market);
+                }
+                info!("  {} ({}): wallet {} {} | compound {} {}",
+                    monitor_addr.name,
+                    monitor_addr.address,
+                    self.format_balance(reading.wallet_balance, divisor),
+                    reading.symbol,
+                    self.format_balance(reading.compound_balance, divisor),
+                    reading.symbol);
+                compound_total = compound_total.saturating_add(reading.compound_balance);
+            }
+
+            info!("───────────────────────────────────────────────────");
+            info!("Subtotal compound balance: {} {} ({})",
+                self.format_balance(compound_total, divisor), first.symbol, compound_total);
+            info!("═══════════════════════════════════════════════════");
+            info!("");
+        }
+
+        Ok(())
+    }
+
+    /// List the known Comet markets on the RPC's chain, reading each one's base
+    /// token to confirm the deployment. Returns the chain id and the markets.
+    async fn discover(&self) -> Result<(u64, Vec<DiscoveredMarket>)> {
+        let chain_id = self.chain_id().await?;
+        let known: Vec<_> = KNOWN_COMET_MARKETS.iter().filter(|(chain, _, _)| *chain == chain_id).collect();
+        if known.is_empty() {
+            let mut chains: Vec<_> = KNOWN_COMET_MARKETS.iter().map(|(chain, _, _)| chain.to_string()).collect();
+            chains.dedup();
+            anyhow::bail!("No known Compound V3 markets on chain {} (known chains: {})", chain_id, chains.join(", "));
+        }
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Compound V3 markets on chain {}", chain_id);
+        info!("═══════════════════════════════════════════════════");
+
+        let mut markets = Vec::with_capacity(known.len());
+        for (_, name, address) in known {
+            let market: H160 = address.parse()
+                .with_context(|| format!("Invalid known market address {}", address))?;
+            let base = match self.base_token_address(market).await {
+                Ok(token) => self.token_metadata(token).await.map(|metadata| (token, metadata)),
+                Err(e) => Err(e),
+            };
+
+            match base {
+                Ok((token, metadata)) => {
+                    info!("{:<10} {} | Base token: {} ({:?})", name, address, metadata.symbol, token);
+                    markets.push(DiscoveredMarket {
+                        name: name.to_string(),
+                        address: address.to_string(),
+                        base_token: Some(format!("{:?}", token)),
+                        base_symbol: Some(metadata.symbol),
+                    });
+                }
+                Err(e) => {
+                    warn!("{:<10} {} | Failed to read base token: {}", name, address, self.config.redact(&format!("{:#}", e)));
+                    markets.push(DiscoveredMarket {
+                        name: name.to_string(),
+                        address: address.to_string(),
+                        base_token: None,
+                        base_symbol: None,
+                    });
+                }
+            }
+        }
+
+        info!("═══════════════════════════════════════════════════");
+        info!("Set market_address (with compound_version \"v3\") in config.json to monitor one of these");
+
+        Ok((chain_id, markets))
+    }
+
+    /// A read-only monitor for a scanned market, sharing this monitor's
+    /// connection. None of the configured market's price feed, USD pricing, cap
+    /// or threshold settings apply, so only raw liquidity is read.
+    fn scan_monitor(&self, market: &ScanMarket) -> Result<Self> {
+        let entry = MarketConfig {
+            address: market.address.clone(),
+            name: None,
+            version: Some(market.version.clone()),
+            // Never compared against; with_shared needs a threshold to parse
+            liquidity_threshold: Some(LiquidityThreshold::Fixed("0".to_string())),
+            utilization_threshold_pct: None,
+            notification_enabled: Some(false),
+            webhook_url: None,
+            alert_cooldown_secs: None,
+            poll_interval_secs: None,
+            price_feed_address: None,
+            token_decimals: None,
+        };
+
+        let mut config = self.config.clone();
+        config.use_market(&entry);
+        config.markets = vec![entry];
+        config.usd_pricing = Some(false);
+        config.threshold_unit = ThresholdUnit::Base;
+        config.cap_headroom_pct = None;
+        config.alert_expression = None;
+
+        self.with_config(config)
+    }
+
+    async fn scan_markets(&self, input: &str, output: &str) -> Result<()> {
+        let mut reader = csv::Reader::from_path(input)
+            .with_context(|| format!("Failed to read {}", input))?;
+        let markets = reader
+            .deserialize::<ScanMarket>()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to parse {}. Expected `address,version` columns.", input))?;
+
+        if markets.is_empty() {
+            info!("No markets found in {}", input);
+            return Ok(());
+        }
+
+        let concurrency = self.config.max_concurrent_requests.unwrap_or(10).max(1);
+        info!("Scanning {} markets ({} concurrent)...", markets.len(), concurrency);
+
+        let results: Vec<ScanResult> = stream::iter(&markets)
+            .map(|market| async move {
+                let reading = async {
+                    let address = market.address.parse::<H160>()
+                        .map_err(|e| anyhow::anyhow!("Invalid market address: {}", e))?;
+                    let monitor = self.scan_monitor(market)?;
+                    match market.version {
+                        CompoundVersion::V2 => monitor.check_liquidity_v2(address).await,
+                        CompoundVersion::V3 => monitor.check_liquidity_v3(address, Some(&market.address)).await,
+                    }
+                }.await;
+
+                let version = match market.version {
+                    CompoundVersion::V2 => "v2",
+                    CompoundVersion::V3 => "v3",
+                };
+
+                match reading {
+                    Ok(snapshot) => ScanResult {
+                        address: market.address.clone(),
+                        version: version.to_string(),
+                        available_liquidity: snapshot.available_liquidity.to_string(),
+                        total_borrows: snapshot.total_borrows.to_string(),
+                        total_reserves: snapshot.reserves.to_string(),
+                        error: String::new(),
+                    },
+                    Err(e) => {
+                        let e = self.config.redact(&format!("{:#}", e));
+                        error!("Failed to check liquidity for {}: {}", market.address, e);
+                        ScanResult {
+                            address: market.address.clone(),
+                            version: version.to_string(),
+                            available_liquidity: String::new(),
+                            total_borrows: String::new(),
+                            total_reserves: String::new(),
+                            error: e,
+                        }
+                    }
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        let mut writer = csv::Writer::from_path(output)
+            .with_context(|| format!("Failed to create {}", output))?;
+        for result in &results {
+            writer.serialize(result)?;
+        }
+        writer.flush()?;
+
+        let failed = results.iter().filter(|r| !r.error.is_empty()).count();
+        info!(
+            "Scan complete: {} succeeded, {} failed. Results written to {}",
+            results.len() - failed, failed, output
+        );
+
+        Ok(())
+    }
+
+    fn format_balance(&self, balance: U256, divisor: U256) -> String {
+        if divisor.is_zero() {
+            return balance.to_string();
+        }
+
+        let whole = balance / divisor;
+        let remainder = balance % divisor;
+
+        if remainder.is_zero() {
+            format!("{}", whole)
+        } else {
+            // Calculate decimal part
+            let decimals_str = format!("{:0width$}", remainder, width = divisor.to_string().len() - 1);
+            let trimmed = decimals_str.trim_end_matches('0');
+            if trimmed.is_empty() {
+                format!("{}", whole)
+            } else {
+                format!("{}.{}", whole, trimmed)
+            }
+        }
+    }
+
+    fn calculate_apy(&self, rate_per_second: u64) -> f64 {
+        // Compound V3 rates are per second with scaling factor
+        // rate_per_second is in units where 1e18 = 100% per second
+        // APY = (1 + rate_per_second)^(seconds_per_year) - 1
+
+        const SECONDS_PER_YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0; // 31,557,600
+        const SCALE: f64 = 1e18;
+
+        let rate = rate_per_second as f64 / SCALE;
+
+        ((1.0 + rate).powf(SECONDS_PER_YEAR) - 1.0) * 100.0
+    }
+
+    fn snapshot_entry(&self, snapshot: &MarketSnapshot) -> SnapshotEntry {
+        SnapshotEntry {
+            market_address: self.config.market_address.clone(),
+            market_symbol: snapshot.symbol.clone(),
+            available_liquidity: snapshot.available_liquidity.to_string(),
+            total_borrows: snapshot.total_borrows.to_string(),
+            total_reserves: snapshot.reserves.to_string(),
+            total_supply: snapshot.total_supply.map(|value| value.to_string()),
+            utilization: snapshot.utilization,
+            supply_apy: snapshot.supply_apy,
+            borrow_apy: snapshot.borrow_apy,
+            base_price_usd: snapshot.base_price_usd,
+            available_liquidity_usd: snapshot.available_liquidity_usd,
+            timestamp: self.config.timestamp_format.format(chrono::Utc::now()),
+        }
+    }
+
+    /// Add a reading to the market's ring buffer of recent readings
+    fn record_history(&self, entry: &SnapshotEntry) {
+        let size = self.config.alert_history_size;
+        if size == 0 {
+            return;
+        }
+
+        let market = self.config.market_address.to_lowercase();
+        let mut states = self.market_state.lock().unwrap();
+        let history = &mut states.entry(market).or_default().history;
+        if history.len() == size {
+            history.pop_front();
+        }
+        history.push_back(entry.clone());
+    }
+
+    fn recent_history(&self) -> Vec<SnapshotEntry> {
+        let market = self.config.market_address.to_lowercase();
+        self.market_state.lock().unwrap()
+            .get(&market)
+            .map(|state| state.history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// POST this tick's snapshots to the snapshot sink in the background, retrying
+    /// with exponential backoff so a slow sink never delays the poll loop
+    fn export_snapshots(&self, markets: Vec<SnapshotEntry>) -> Option<tokio::task::JoinHandle<()>> {
+        let url = self.config.snapshot_sink_url.clone()?;
+        if markets.is_empty() {
+            return None;
+        }
+
+        if let Some(path) = url.expose().strip_prefix("file://") {
+            for entry in &markets {
+                if let Err(e) = append_json_line(path, entry) {
+                    error!("Snapshot export to {} failed: {:#}", url, e);
+                    break;
+                }
+            }
+            return None;
+        }
+
+        let client = self.client.clone();
+        let max_retries = self.config.snapshot_sink_max_retries.unwrap_or(3);
+
+        Some(tokio::spawn(async move {
+            let body = serde_json::json!({ "markets": markets });
+            let mut delay = Duration::from_millis(500);
+
+            for attempt in 0..=max_retries {
+                let result = client
+                    .post(url.expose())
+                    .json(&body)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+
+                match result {
+                    Ok(_) => {
+                        debug!("Exported {} snapshot(s) to {}", markets.len(), url);
+                        return;
+                    }
+                    Err(e) if attempt < max_retries => {
+                        warn!(
+                            "Snapshot export failed (attempt {}/{}): {}. Retrying in {:?}",
+                            attempt + 1, max_retries + 1, e.without_url(), delay
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                    }
+                    Err(e) => {
+                        error!("Snapshot export to {} failed: {}", url, e.without_url());
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Evaluate the configured alert_expression against a snapshot.
+    /// Fields that are unavailable for the market (e.g. APY on V2) are left undefined.
+    fn evaluate_alert_expression(&self, expression: &evalexpr::Node, snapshot: &MarketSnapshot, threshold: U256) -> Result<bool> {
+        use evalexpr::{ContextWithMutableVariables, HashMapContext, Value};
+
+        // U256 values are exposed as floats; precision loss is irrelevant for comparisons
+        let as_float = u256_to_f64;
+
+        let mut context = HashMapContext::new();
+        context.set_value("liquidity".into(), Value::Float(as_float(snapshot.available_liquidity)))?;
+        context.set_value("borrows".into(), Value::Float(as_float(snapshot.total_borrows)))?;
+        context.set_value("reserves".into(), Value::Float(i256_to_f64(snapshot.reserves)))?;
+        context.set_value("threshold".into(), Value::Float(as_float(threshold)))?;
+        if let Some(total_supply) = snapshot.total_supply {
+            context.set_value("total_supply".into(), Value::Float(as_float(total_supply)))?;
+        }
+        if let Some(utilization) = snapshot.utilization {
+            context.set_value("utilization".into(), Value::Float(utilization))?;
+        }
+        if let Some(supply_apy) = snapshot.supply_apy {
+            context.set_value("supply_apy".into(), Value::Float(supply_apy))?;
+        }
+        if let Some(borrow_apy) = snapshot.borrow_apy {
+            context.set_value("borrow_apy".into(), Value::Float(borrow_apy))?;
+        }
+        if let Some(price) = snapshot.base_price_usd {
+            context.set_value("price".into(), Value::Float(price))?;
+        }
+        if let Some(liquidity_usd) = snapshot.available_liquidity_usd {
+            context.set_value("liquidity_usd".into(), Value::Float(liquidity_usd))?;
+        }
+
+        Ok(expression.eval_boolean_with_context(&context)?)
+    }
+
+    /// Decide whether a snapshot should trigger an alert against `threshold`,
+    /// returning the alert message if so
+    fn alert_condition(&self, snapshot: &MarketSnapshot, threshold: U256) -> Option<String> {
+        match (&self.alert_expression, &self.config.alert_expression) {
+            (Some(expression), Some(source)) => {
+                match self.evaluate_alert_expression(expression, snapshot, threshold) {
+                    Ok(true) => Some(format!("Alert expression matched: {}", source)),
+                    Ok(false) => None,
+                    Err(e) => {
+                        error!("Failed to evaluate alert_expression: {}", e);
+                        None
+                    }
+                }
+            }
+            _ if self.config.threshold_mode == ThresholdMode::Utilization => {
+                let limit = self.config.utilization_threshold_pct?;
+                match snapshot.utilization {
+                    Some(utilization) if utilization > limit => Some(format!(
+                        "Utilization ({:.2}%) is above threshold ({}%)",
+                        utilization, limit
+                    )),
+                    Some(_) => None,
+                    None => {
+                        error!("Utilization unavailable, cannot evaluate utilization threshold");
+                        None
+                    }
+                }
+            }
+            _ => match self.config.threshold_unit {
+                ThresholdUnit::Base => {
+                    if snapshot.available_liquidity < threshold {
+                        Some(format!(
+                            "Available liquidity ({}) is below threshold ({})",
+                            snapshot.available_liquidity, threshold
+                        ))
+                    } else {
+                        None
+                    }
+                }
+                ThresholdUnit::Usd => match snapshot.available_liquidity_usd {
+                    Some(liquidity_usd) if liquidity_usd < u256_to_f64(threshold) => Some(format!(
+                        "Available liquidity (${:.2}) is below threshold (${})",
+                        liquidity_usd, threshold
+                    )),
+                    Some(_) => None,
+                    None => {
+                        error!("USD liquidity unavailable, cannot evaluate USD threshold");
+                        None
+                    }
+                },
+            },
+        }
+    }
+
+    /// Message for the notification that the liquidity alert condition has cleared
+    fn recovery_message(&self, snapshot: &MarketSnapshot, threshold: U256) -> String {
+        match (&self.config.alert_expression, self.config.threshold_unit, snapshot.available_liquidity_usd) {
+            (Some(source), _, _) => format!("Liquidity recovered: alert expression no longer matches: {}", source),
+            (None, _, _) if self.config.threshold_mode == ThresholdMode::Utilization => format!(
+                "Liquidity recovered: utilization ({:.2}%) is back below threshold ({}%)",
+                snapshot.utilization.unwrap_or_default(), self.config.utilization_threshold_pct.unwrap_or_default()
+            ),
+            (None, ThresholdUnit::Usd, Some(liquidity_usd)) => format!(
+                "Liquidity recovered: available liquidity (${:.2}) is back above threshold (${})",
+                liquidity_usd, threshold
+            ),
+            _ => format!(
+                "Liquidity recovered: available liquidity ({}) is back above threshold ({})",
+                snapshot.available_liquidity, threshold
+            ),
+        }
+    }
+
+    /// Record whether the market is in breach, returning whether it was at the
+    /// previous poll
+    fn set_in_breach(&self, in_breach: bool) -> bool {
+        let mut states = self.market_state.lock().unwrap();
+        let state = states.entry(self.config.market_address.to_lowercase()).or_default();
+        std::mem::replace(&mut state.in_breach, in_breach)
+    }
+
+    /// Compare the V3 token balance with the protocol's accounting figure
+    /// (totalSupply - totalBorrow + reserves), returning a message if they
+    /// differ by more than accounting_tolerance_pct
+    fn accounting_divergence(&self, snapshot: &MarketSnapshot) -> Option<String> {
+        if self.config.compound_version != CompoundVersion::V3 {
+            return None;
+        }
+        let tolerance_pct = self.config.accounting_tolerance_pct?;
+        let total_supply = snapshot.total_supply?;
+
+        let signed = |value: U256| I256::try_from(value).unwrap_or(I256::MAX);
+        let accounting = signed(total_supply)
+            .saturating_sub(signed(snapshot.total_borrows))
+            .saturating_add(snapshot.reserves);
+        let balance = signed(snapshot.available_liquidity);
+
+        let difference = balance.saturating_sub(accounting).unsigned_abs();
+        let divergence_pct = percentage(difference, snapshot.available_liquidity)
+            .unwrap_or(if difference.is_zero() { 0.0 } else { f64::INFINITY });
+
+        debug!("Accounting liquidity: {} | Token balance: {} | Divergence: {:.4}%",
+            self.log_signed(accounting), self.log_number(snapshot.available_liquidity), divergence_pct);
+
+        (divergence_pct > tolerance_pct).then(|| format!(
+            "Token balance ({}) differs from accounting liquidity ({}) by {} ({:.4}%, tolerance {}%)",
+            snapshot.available_liquidity, accounting, difference, divergence_pct, tolerance_pct
+        ))
+    }
+
+    /// Record this reading's available liquidity, returning the fall since the
+    /// previous poll when it is more than liquidity_drop_pct. Nothing is returned
+    /// until warmup_reads readings have been collected.
+    fn liquidity_drop(&self, snapshot: &MarketSnapshot) -> Option<LiquidityDrop> {
+        let now = Instant::now();
+        let (previous, reads) = {
+            let mut states = self.market_state.lock().unwrap();
+            let state = states.entry(self.config.market_address.to_lowercase()).or_default();
+            state.reads += 1;
+            (state.previous_liquidity.replace((snapshot.available_liquidity, now)), state.reads)
+        };
+
+        let limit_pct = self.config.liquidity_drop_pct?;
+        let warmup_reads = self.warmup_reads();
+        if reads <= warmup_reads {
+            info!("Rapid drain alerts for {} warming up: {}/{} reading(s)", self.market_label(), reads, warmup_reads);
+            return None;
+        }
+        let (from, read_at) = previous?;
+        let to = snapshot.available_liquidity;
+        let drop_pct = percentage(from.saturating_sub(to), from)?;
+
+        (drop_pct > limit_pct).then(|| LiquidityDrop {
+            from: from.to_string(),
+            to: to.to_string(),
+            drop_pct,
+            elapsed_secs: now.duration_since(read_at).as_secs(),
+        })
+    }
+
+    fn warmup_reads(&self) -> u64 {
+        self.config.warmup_reads.unwrap_or(1).max(1)
+    }
+
+    /// APY alerts for supply_apy_min and borrow_apy_max, with the alert types
+    /// whose condition has cleared
+    fn apy_alerts(&self, snapshot: &MarketSnapshot) -> (Vec<(AlertType, String)>, Vec<AlertType>) {
+        let checks = [
+            (AlertType::SupplyApy, "Supply APY", snapshot.supply_apy, self.config.supply_apy_min, true),
+            (AlertType::BorrowApy, "Borrow APY", snapshot.borrow_apy, self.config.borrow_apy_max, false),
+        ];
+
+        let mut alerts = Vec::new();
+        let mut cleared = Vec::new();
+        for (alert_type, label, apy, limit, is_min) in checks {
+            let (Some(apy), Some(limit)) = (apy, limit) else {
+                continue;
+            };
+
+            if (is_min && apy < limit) || (!is_min && apy > limit) {
+                let message = format!(
+                    "{} ({:.2}%) is {} the {} of {:.2}%",
+                    label, apy, if is_min { "below" } else { "above" }, if is_min { "minimum" } else { "maximum" }, limit
+                );
+                alerts.push((alert_type, message));
+            } else {
+                cleared.push(alert_type);
+            }
+        }
+
+        (alerts, cleared)
+    }
+
+    /// Markets whose supply or borrows are within cap_headroom_pct of their cap,
+    /// with the cap alert types whose condition has cleared
+    fn cap_alerts(&self, snapshot: &MarketSnapshot) -> (Vec<(AlertType, CapUsage, String)>, Vec<AlertType>) {
+        let Some(headroom_pct) = self.config.cap_headroom_pct else {
+            return (Vec::new(), Vec::new());
+        };
+
+        // V2 supply is the cToken supply at the stored exchange rate, as logged
+        let checks = [
+            (AlertType::SupplyCap, "Total supply", snapshot.total_supply, snapshot.supply_cap),
+            (AlertType::BorrowCap, "Total borrows", Some(snapshot.total_borrows), snapshot.borrow_cap),
+        ];
+
+        let mut alerts = Vec::new();
+        let mut cleared = Vec::new();
+        for (alert_type, label, current, cap) in checks {
+            // A cap of zero means the market is uncapped
+            let Some((current, cap, usage_pct)) = current.zip(cap)
+                .and_then(|(current, cap)| Some((current, cap, percentage(current, cap)?)))
+            else {
+                continue;
+            };
+
+            if usage_pct >= 100.0 - headroom_pct {
+                let headroom = cap.saturating_sub(current);
+                let message = format!(
+                    "{} ({}) is at {:.2}% of the cap ({}), headroom {}",
+                    label, current, usage_pct, cap, headroom
+                );
+                let usage = CapUsage {
+                    current: current.to_string(),
+                    cap: cap.to_string(),
+                    headroom: headroom.to_string(),
+                    usage_pct,
+                };
+                alerts.push((alert_type, usage, message));
+            } else {
+                cleared.push(alert_type);
+            }
+        }
+
+        (alerts, cleared)
+    }
+
+    /// Build a gas alert if the monitored signer's ETH balance is below the minimum
+    async fn check_signer_gas(&self, snapshot: &MarketSnapshot) -> Result<Option<LiquidityAlert>> {
+        let Some(signer) = self.monitored_signer()? else {
+            return Ok(None);
+        };
+        let Some((balance, minimum)) = self.low_signer_balance(signer).await? else {
+            self.reset_cooldown(AlertType::SignerGas);
+            return Ok(None);
+        };
+
+        let wei = U256::exp10(18);
+        let message = format!(
+            "Signer {:?} ETH balance ({} ETH) is below the minimum for gas ({} ETH)",
+            signer, self.format_balance(balance, wei), self.format_balance(minimum, wei)
+        );
+        warn!("{}", message);
+
+        let mut alert = self.build_alert(snapshot, AlertType::SignerGas, message);
+        alert.signer_balance = Some(SignerBalance {
+            address: format!("{:?}", signer),
+            balance: balance.to_string(),
+            minimum: minimum.to_string(),
+        });
+        Ok(Some(alert))
+    }
+
+    /// Build an alert for each of health_addresses whose health ratio is below
+    /// health_threshold (V3)
+    async fn check_account_health(&self, snapshot: &MarketSnapshot) -> Vec<LiquidityAlert> {
+        let Some(threshold) = self.config.health_threshold else {
+            return Vec::new();
+        };
+        if self.config.compound_version != CompoundVersion::V3 || self.config.health_addresses.is_empty() {
+            return Vec::new();
+        }
+
+        let Ok(market_address) = self.config.market_address.parse::<H160>() else {
+            return Vec::new();
+        };
+        let contract = Comet::new(market_address, Arc::clone(&self.provider));
+
+        let mut alerts = Vec::new();
+        for address in &self.config.health_addresses {
+            let position = match address.parse::<H160>() {
+                Ok(account) => self.borrow_position(&contract, account).await,
+                Err(e) => Err(anyhow::Error::new(e).context("Invalid address")),
+            };
+            let position = match position {
+                Ok(position) => position,
+                Err(e) => {
+                    error!("Failed to check health of {}: {}", address, self.config.redact(&format!("{:#}", e)));
+                    continue;
+                }
+            };
+
+            let Some(ratio) = position.health_ratio() else {
+                debug!("{} has no debt", address);
+                self.reset_subject_cooldown(AlertType::AccountHealth, &address.to_lowercase());
+                continue;
+            };
+            info!("Health ratio of {}: {:.3}", address, ratio);
+            if ratio >= threshold {
+                self.reset_subject_cooldown(AlertType::AccountHealth, &address.to_lowercase());
+                continue;
+            }
+
+            let message = format!(
+                "Account {} health ratio is {:.3}, below the threshold of {} (debt ${:.2}, liquidation limit ${:.2})",
+                address, ratio, threshold, position.debt_usd, position.liquidation_limit_usd
+            );
+            warn!("{}", message);
+
+            let mut alert = self.build_alert(snapshot, AlertType::AccountHealth, message);
+            alert.account_health = Some(AccountHealth {
+                address: address.clone(),
+                health_ratio: ratio,
+                debt_usd: position.debt_usd,
+                liquidation_limit_usd: position.liquidation_limit_usd,
+                health_threshold: threshold,
+            });
+            alerts.push(alert);
+        }
+
+        alerts
+    }
+
+    fn build_alert(&self, snapshot: &MarketSnapshot, alert_type: AlertType, message: String) -> LiquidityAlert {
+        LiquidityAlert {
+            alert_type,
+            market_address: self.config.market_address.clone(),
+            market_name: self.config.market_name.clone(),
+            market_symbol: snapshot.symbol.clone(),
+            available_liquidity: snapshot.available_liquidity.to_string(),
+            total_borrows: snapshot.total_borrows.to_string(),
+            total_reserves: snapshot.reserves.to_string(),
+            threshold: self.threshold_display(),
+            utilization: snapshot.utilization,
+            base_price_usd: snapshot.base_price_usd,
+            available_liquidity_usd: snapshot.available_liquidity_usd,
+            timestamp: self.config.timestamp_format.format(chrono::Utc::now()),
+            message,
+            cap_usage: None,
+            signer_balance: None,
+            account_health: None,
+            liquidity_drop: None,
+            supply_apy: snapshot.supply_apy,
+            borrow_apy: snapshot.borrow_apy,
+            reserves_negative: snapshot.reserves.is_negative(),
+            recovered: false,
+            recent_history: self.recent_history(),
+            low_water_mark: None,
+            high_water_mark: None,
+        }
+    }
+
+    /// Send an alert if notifications are enabled and the startup grace period has
+    /// passed, logging any delivery failure
+    async fn dispatch_alert(&self, alert: LiquidityAlert) {
+        if !self.config.notification_enabled.unwrap_or(true) {
+            info!("Notification disabled, skipping alert");
+            self.log_decision(&alert, Decision::Suppressed, "notifications disabled".to_string());
+            return;
+        }
+
+        let grace = Duration::from_secs(self.config.startup_grace_secs);
+        let elapsed = self.started_at.elapsed();
+        if elapsed < grace {
+            info!(
+                "Within startup grace period ({}s remaining), suppressing alert",
+                (grace - elapsed).as_secs()
+            );
+            let reason = format!("startup grace period ({}s remaining)", (grace - elapsed).as_secs());
+            self.log_decision(&alert, Decision::Suppressed, reason);
+            return;
+        }
+
+        // Recoveries are one per breach and don't count towards the cooldown
+        if let Some(remaining) = self.cooldown_remaining(alert.alert_type, &alert_subject(&alert)).filter(|_| !alert.recovered) {
+            info!("Alert cooldown active ({}s remaining), suppressing alert", remaining.as_secs());
+            let reason = format!("cooldown ({}s remaining)", remaining.as_secs());
+            self.log_decision(&alert, Decision::Suppressed, reason);
+            return;
+        }
+
+        let hash = self.alert_hash(&alert);
+        if let Some(age) = self.duplicate_age(hash) {
+            info!("Identical alert sent {}s ago, suppressing duplicate", age.as_secs());
+            self.log_decision(&alert, Decision::Suppressed, format!("duplicate of an alert sent {}s ago", age.as_secs()));
+            return;
+        }
+
+        match self.send_alert(&alert).await {
+            Ok(()) if alert.recovered => {
+                self.record_alert_hash(hash);
+                self.log_decision(&alert, Decision::Sent, "recovered".to_string());
+            }
+            Ok(()) => {
+                self.record_alert_hash(hash);
+                self.market_state.lock().unwrap()
+                    .entry(self.config.market_address.to_lowercase())
+                    .or_default()
+                    .last_alert_sent
+                    .insert((alert.alert_type, alert_subject(&alert)), Instant::now());
+                self.log_decision(&alert, Decision::Sent, "condition met".to_string());
+            }
+            Err(e) => {
+                let e = self.config.redact(&e.to_string());
+                error!("Failed to send alert: {}", e);
+                self.log_decision(&alert, Decision::Failed, e);
+            }
+        }
+    }
+
+    /// Stable hash of what an alert says: its market, type (recoveries apart)
+    /// and available liquidity rounded down to a dedup_bucket_size bucket
+    fn alert_hash(&self, alert: &LiquidityAlert) -> u64 {
+        let bucket_size = self.config.dedup_bucket_size.as_deref()
+            .and_then(|size| U256::from_dec_str(size).ok())
+            .filter(|size| !size.is_zero())
+            .unwrap_or(U256::one());
+        let bucket = U256::from_dec_str(&alert.available_liquidity)
+            .map(|liquidity| liquidity / bucket_size)
+            .unwrap_or_default();
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        alert.market_address.to_lowercase().hash(&mut hasher);
+        alert.alert_type.hash(&mut hasher);
+        alert.recovered.hash(&mut hasher);
+        bucket.hash(&mut hasher);
+        // Alerts about different accounts or caps are different states; the
+        // alert type already tells supply and borrow caps apart
+        if let Some(ref health) = alert.account_health {
+            health.address.to_lowercase().hash(&mut hasher);
+        }
+        if let Some(ref usage) = alert.cap_usage {
+            usage.cap.hash(&mut hasher);
+        }
+        if let Some(ref signer) = alert.signer_balance {
+            signer.address.to_lowercase().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// How long ago an alert with this hash was sent, if within dedup_window_secs
+    fn duplicate_age(&self, hash: u64) -> Option<Duration> {
+        let window = Duration::from_secs(self.config.dedup_window_secs.unwrap_or(0));
+        let sent_at = *self.sent_alert_hashes.lock().unwrap().get(&hash)?;
+        Some(sent_at.elapsed()).filter(|&age| age < window)
+    }
+
+    /// Remember a sent alert's hash, dropping hashes older than the window
+    fn record_alert_hash(&self, hash: u64) {
+        let window = Duration::from_secs(self.config.dedup_window_secs.unwrap_or(0));
+        if window.is_zero() {
+            return;
+        }
+        let mut hashes = self.sent_alert_hashes.lock().unwrap();
+        hashes.retain(|_, sent_at| sent_at.elapsed() < window);
+        hashes.insert(hash, Instant::now());
+    }
+
+    /// Time left before another alert of this type about `subject` may be sent
+    /// for the market, if alert_cooldown_secs is set and one was sent recently
+    fn cooldown_remaining(&self, alert_type: AlertType, subject: &str) -> Option<Duration> {
+        let cooldown = Duration::from_secs(self.config.alert_cooldown_secs.unwrap_or(0));
+        let states = self.market_state.lock().unwrap();
+        let sent_at = states.get(&self.config.market_address.to_lowercase())?
+            .last_alert_sent
+            .get(&(alert_type, subject.to_string()))?;
+        cooldown.checked_sub(sent_at.elapsed()).filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Clear the cooldown of an alert type once its condition has cleared, so a
+    /// fresh breach alerts immediately
+    fn reset_cooldown(&self, alert_type: AlertType) {
+        self.reset_subject_cooldown(alert_type, "");
+    }
+
+    /// reset_cooldown for alerts about one subject, e.g. an account in
+    /// health_addresses
+    fn reset_subject_cooldown(&self, alert_type: AlertType, subject: &str) {
+        let mut states = self.market_state.lock().unwrap();
+        let Some(state) = states.get_mut(&self.config.market_address.to_lowercase()) else {
+            return;
+        };
+        let removed = state.last_alert_sent.remove(&(alert_type, subject.to_string())).is_some();
+        if removed && self.config.alert_cooldown_secs.unwrap_or(0) > 0 {
+            match subject {
+                "" => info!("Alert condition cleared, {:?} alert cooldown reset", alert_type),
+                subject => info!("Alert condition cleared for {}, {:?} alert cooldown reset", subject, alert_type),
+            }
+        }
+    }
+
+    /// Record an alert decision in the decision log, if configured
+    fn log_decision(&self, alert: &LiquidityAlert, decision: Decision, reason: String) {
+        let Some(ref path) = self.config.decision_log_path else {
+            return;
+        };
+
+        let event = DecisionEvent {
+            timestamp: self.config.timestamp_format.format(chrono::Utc::now()),
+            market_address: alert.market_address.clone(),
+            alert_type: alert.alert_type,
+            decision,
+            reason,
+            message: alert.message.clone(),
+        };
+        if let Err(e) = append_json_line(path, &event) {
+            warn!("Failed to write decision log: {:#}", e);
+        }
+    }
+
+    /// Re-read config.json on SIGHUP and apply each market's liquidity_threshold
+    /// and poll interval, returning the poll interval of each of `markets`.
+    /// Other changed fields are reported as requiring a restart.
+    fn reload(&self, markets: &[CompoundMonitor], poll_intervals: &[u64]) -> Result<Vec<u64>> {
+        info!("SIGHUP received, reloading configuration");
+
+        let mut config = Config::load(&self.config.config_path)?;
+        config.validate()?;
+        if let Some(ref market) = self.config.selected_market {
+            config.select_market(market)?;
+        }
+        let entries: Vec<Option<&MarketConfig>> = markets
+            .iter()
+            .map(|monitor| config.markets.iter()
+                .find(|entry| entry.address.eq_ignore_ascii_case(&monitor.config.market_address)))
+            .collect();
+        let thresholds = markets
+            .iter()
+            .zip(&entries)
+            .map(|(monitor, entry)| {
+                match entry.and_then(|entry| entry.liquidity_threshold.as_ref()) {
+                    Some(threshold) => Thresholds::parse(threshold, monitor.config.token_decimals).map(Some),
+                    None => Ok(None),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let current = Self::reload_view(&self.config)?;
+        let reloaded = Self::reload_view(&config)?;
+        if let (Some(current), Some(reloaded)) = (current.as_object(), reloaded.as_object()) {
+            let mut fields: Vec<&String> = current.keys().chain(reloaded.keys()).collect();
+            fields.sort_unstable();
+            fields.dedup();
+
+            for field in fields {
+                if current.get(field) != reloaded.get(field) {
+                    warn!("{} changed in config.json; restart to apply", field);
+                }
+            }
+        }
+
+        // A market removed from config.json keeps its interval until restart
+        let reloaded_intervals: Vec<u64> = entries
+            .iter()
+            .zip(poll_intervals)
+            .map(|(entry, &current)| entry.and_then(|entry| entry.poll_interval_secs).unwrap_or(current))
+            .collect();
+        for ((monitor, &current), &reloaded) in markets.iter().zip(poll_intervals).zip(&reloaded_intervals) {
+            if reloaded != current {
+                info!("Poll interval changed for {}: {}s -> {}s", monitor.market_label(), current, reloaded);
+                // The previous reading no longer spans a poll interval
+                if let Some(state) = self.market_state.lock().unwrap().get_mut(&monitor.config.market_address.to_lowercase()) {
+                    state.previous_liquidity = None;
+                    state.reads = 0;
+                }
+            }
+        }
+
+        for (monitor, thresholds) in markets.iter().zip(thresholds) {
+            // A market removed from config.json keeps running until restart
+            let Some(thresholds) = thresholds else { continue };
+
+            let mut active = monitor.thresholds.lock().unwrap();
+            if *active != thresholds {
+                let now = chrono::Utc::now();
+                info!("liquidity_threshold changed for {}: {} -> {} (default {} -> {}, {} schedule window(s))",
+                    monitor.market_label(), active.at(now), thresholds.at(now), active.default, thresholds.default,
+                    thresholds.schedule.len());
+            }
+            *active = thresholds;
+        }
+
+        info!("Configuration reloaded");
+        Ok(reloaded_intervals)
+    }
+
+    /// The config as compared on reload, without the fields applied live
+    fn reload_view(config: &Config) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(config)?;
+        if let Some(fields) = value.as_object_mut() {
+            for field in ["poll_interval_secs", "liquidity_threshold"] {
+                fields.remove(field);
+            }
+            if let Some(markets) = fields.get_mut("markets").and_then(|markets| markets.as_array_mut()) {
+                for market in markets.iter_mut().filter_map(|market| market.as_object_mut()) {
+                    market.remove("liquidity_threshold");
+                    market.remove("poll_interval_secs");
+                }
+            }
+            if let Some(defaults) = fields.get_mut("defaults").and_then(|defaults| defaults.as_object_mut()) {
+                defaults.remove("poll_interval_secs");
+            }
+        }
+        Ok(value)
+    }
+
+    /// Indexes of the next `per_tick` of `count` markets in the round-robin
+    /// rotation, advancing the cursor past them
+    fn next_rotation(&self, count: usize, per_tick: usize) -> Vec<usize> {
+        use std::sync::atomic::Ordering;
+
+        let start = self.rotation_cursor.load(Ordering::Relaxed) % count;
+        self.rotation_cursor.store((start + per_tick) % count, Ordering::Relaxed);
+        (start..start + per_tick).map(|index| index % count).collect()
+    }
+
+    /// This market's poll_interval_secs, falling back to the top-level one
+    fn poll_interval_secs(&self) -> u64 {
+        self.config.markets.first()
+            .and_then(|market| market.poll_interval_secs)
+            .unwrap_or(self.config.poll_interval_secs)
+    }
+
+    /// Log the settings that apply to this monitor's market
+    fn log_market_settings(&self) {
+        let version_str = match self.config.compound_version {
+            CompoundVersion::V2 => "V2",
+            CompoundVersion::V3 => "V3 (Comet)",
+        };
+
+        info!("Market: {} [Compound {}]", self.market_label(), version_str);
+        let threshold = self.thresholds.lock().unwrap().default;
+        match (self.config.threshold_mode, self.config.threshold_unit) {
+            (ThresholdMode::Utilization, _) => {
+                info!("  Threshold: utilization above {}%", self.config.utilization_threshold_pct.unwrap_or_default());
+            }
+            (ThresholdMode::Liquidity, ThresholdUnit::Base) => info!("  Threshold: {}", threshold),
+            (ThresholdMode::Liquidity, ThresholdUnit::Usd) => info!("  Threshold: ${}", threshold),
+        }
+        info!("  Notifications: {}", if self.config.notification_enabled.unwrap_or(true) { "enabled" } else { "disabled" });
+        if let Some(cooldown) = self.config.alert_cooldown_secs.filter(|&secs| secs > 0) {
+            info!("  Alert cooldown: {}s", cooldown);
+        }
+        if self.poll_interval_secs() != self.config.poll_interval_secs {
+            info!("  Poll interval: {}s", self.poll_interval_secs());
+        }
+        if let Some(window) = self.config.dedup_window_secs.filter(|&secs| secs > 0) {
+            info!("  Alert dedup: {}s window, bucket size {}", window, self.config.dedup_bucket_size.as_deref().unwrap_or("1"));
+        }
+        let schedule_len = self.thresholds.lock().unwrap().schedule.len();
+        if schedule_len > 0 {
+            info!("  Threshold schedule: {} window(s), current threshold {}", schedule_len, self.threshold());
+        }
+        if let Some(headroom_pct) = self.config.cap_headroom_pct {
+            if self.config.compound_version == CompoundVersion::V3 {
+                warn!("  cap_headroom_pct is ignored for V3: Comet has no base asset supply/borrow caps");
+            } else {
+                info!("  Cap alerts: within {}% of supply/borrow caps", headroom_pct);
+            }
+        }
+        if self.config.supply_apy_min.is_some() || self.config.borrow_apy_max.is_some() {
+            if self.config.compound_version == CompoundVersion::V3 {
+                let limit = |limit: Option<f64>| limit.map_or("-".to_string(), |limit| format!("{}%", limit));
+                info!("  APY alerts: supply below {}, borrow above {}",
+                    limit(self.config.supply_apy_min), limit(self.config.borrow_apy_max));
+            } else {
+                warn!("  supply_apy_min/borrow_apy_max are ignored for V2: APY is only read from V3 markets");
+            }
+        }
+        if self.config.negative_reserves_alert.unwrap_or(false) {
+            if self.config.compound_version == CompoundVersion::V3 {
+                info!("  Negative reserves alerts: enabled");
+            } else {
+                warn!("  negative_reserves_alert is ignored for V2: V2 reserves cannot go negative");
+            }
+        }
+        if let Some(drop_pct) = self.config.liquidity_drop_pct {
+            info!("  Rapid drain alerts: liquidity falling more than {}% between polls, after {} warm-up reading(s)",
+                drop_pct, self.warmup_reads());
+        }
+        if let Some(tolerance_pct) = self.config.accounting_tolerance_pct {
+            if self.config.compound_version == CompoundVersion::V3 {
+                info!("  Accounting check: token balance vs accounting within {}%{}",
+                    tolerance_pct, if self.config.accounting_alert { " (alerting)" } else { "" });
+            } else {
+                warn!("  accounting_tolerance_pct is ignored for V2: only V3 reports total supply");
+            }
+        }
+    }
+
+    /// Run all checks for this monitor's market once, returning the reading for
+    /// the snapshot sink
+    async fn poll(&self) -> Option<SnapshotEntry> {
+        self.refresh_threshold().await;
+
+        let snapshot = match self.check_liquidity().await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!("Failed to check liquidity for {}: {}", self.market_label(), self.config.redact(&e.to_string()));
+                return None;
+            }
+        };
+
+        let entry = self.snapshot_entry(&snapshot);
+        self.record_history(&entry);
+        self.market_state.lock().unwrap()
+            .entry(self.config.market_address.to_lowercase())
+            .or_default()
+            .last_snapshot = Some(snapshot.clone());
+
+        let water_marks = self.record_water_marks(&snapshot);
+        info!("Low-water mark: {} | High-water mark: {}",
+            self.log_number(water_marks.low), self.log_number(water_marks.high));
+
+        if let Some(message) = self.alert_condition(&snapshot, self.threshold()) {
+            warn!(
+                event = "threshold_crossed",
+                market = %self.config.market_address,
+                symbol = %snapshot.symbol,
+                available_liquidity = %snapshot.available_liquidity,
+                threshold = %self.threshold_display(),
+                "Alert condition met! {}", message
+            );
+
+            let mut alert = self.build_alert(&snapshot, AlertType::Liquidity, message);
+            alert.low_water_mark = Some(water_marks.low.to_string());
+            alert.high_water_mark = Some(water_marks.high.to_string());
+            self.dispatch_alert(alert).await;
+            self.set_in_breach(true);
+        } else {
+            self.reset_cooldown(AlertType::Liquidity);
+
+            if self.set_in_breach(false) && self.config.recovery_alerts.unwrap_or(true) {
+                let message = self.recovery_message(&snapshot, self.threshold());
+                info!(
+                    event = "recovered",
+                    market = %self.config.market_address,
+                    symbol = %snapshot.symbol,
+                    available_liquidity = %snapshot.available_liquidity,
+                    threshold = %self.threshold_display(),
+                    "{}", message
+                );
+
+                let mut alert = self.build_alert(&snapshot, AlertType::Liquidity, message);
+                alert.recovered = true;
+                alert.low_water_mark = Some(water_marks.low.to_string());
+                alert.high_water_mark = Some(water_marks.high.to_string());
+                self.dispatch_alert(alert).await;
+            }
+        }
+
+        if let Some(message) = self.accounting_divergence(&snapshot) {
+            warn!("Accounting divergence! {}", message);
+
+            if self.config.accounting_alert {
+                let alert = self.build_alert(&snapshot, AlertType::AccountingDivergence, message);
+                self.dispatch_alert(alert).await;
+            }
+        } else {
+            self.reset_cooldown(AlertType::AccountingDivergence);
+        }
+
+        let (apy_alerts, apy_cleared) = self.apy_alerts(&snapshot);
+        for (alert_type, message) in apy_alerts {
+            warn!("APY out of range! {}", message);
+
+            let alert = self.build_alert(&snapshot, alert_type, message);
+            self.dispatch_alert(alert).await;
+        }
+        for alert_type in apy_cleared {
+            self.reset_cooldown(alert_type);
+        }
+
+        if self.config.negative_reserves_alert.unwrap_or(false) && self.config.compound_version == CompoundVersion::V3 {
+            if snapshot.reserves.is_negative() {
+                let message = format!("Reserves are negative ({}): the protocol is in deficit", snapshot.reserves);
+                warn!("Negative reserves! {}", message);
+
+                let alert = self.build_alert(&snapshot, AlertType::NegativeReserves, message);
+                self.dispatch_alert(alert).await;
+            } else {
+                self.reset_cooldown(AlertType::NegativeReserves);
+            }
+        }
+
+        match self.liquidity_drop(&snapshot) {
+            Some(drop) => {
+                let message = format!(
+                    "Available liquidity fell {:.2}% in {}s, from {} to {} (limit {}%)",
+                    drop.drop_pct, drop.elapsed_secs, drop.from, drop.to, self.config.liquidity_drop_pct.unwrap_or_default()
+                );
+                warn!("Rapid drain! {}", message);
+
+                let mut alert = self.build_alert(&snapshot, AlertType::RapidDrain, message);
+                alert.liquidity_drop = Some(drop);
+                self.dispatch_alert(alert).await;
+            }
+            None => self.reset_cooldown(AlertType::RapidDrain),
+        }
+
+        let (cap_alerts, caps_cleared) = self.cap_alerts(&snapshot);
+        for (alert_type, usage, message) in cap_alerts {
+            warn!("Approaching cap! {}", message);
+
+            let mut alert = self.build_alert(&snapshot, alert_type, message);
+            alert.cap_usage = Some(usage);
+            self.dispatch_alert(alert).await;
+        }
+        for alert_type in caps_cleared {
+            self.reset_cooldown(alert_type);
+        }
+
+        match self.check_signer_gas(&snapshot).await {
+            Ok(Some(alert)) => self.dispatch_alert(alert).await,
+            Ok(None) => {}
+            Err(e) => error!("Failed to check signer gas balance: {}", self.config.redact(&e.to_string())),
+        }
+
+        for alert in self.check_account_health(&snapshot).await {
+            self.dispatch_alert(alert).await;
+        }
+
+        Some(entry)
+    }
+
+    /// One reading of every configured market against its threshold, without
+    /// alerting. Fails with ThresholdBreached if any market is in breach.
+    async fn check(&self) -> Result<()> {
+        let markets = self.config.markets
+            .iter()
+            .map(|market| self.for_market(market))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut results = Vec::new();
+        for market in &markets {
+            market.refresh_threshold().await;
+            let snapshot = market.check_liquidity().await
+                .with_context(|| format!("Failed to check liquidity for {}", market.market_label()))?;
+            let breach = market.alert_condition(&snapshot, market.threshold());
+            results.push((market, snapshot, breach));
+        }
+
+        info!("═══════════════════════════════════════════════════");
+        for (market, snapshot, breach) in &results {
+            match breach {
+                Some(message) => warn!("✗ {} ({}): {}", market.market_label(), snapshot.symbol, message),
+                None => info!(
+                    "✓ {} ({}): {} available, threshold {}",
+                    market.market_label(), snapshot.symbol, market.log_number(snapshot.available_liquidity), market.threshold_display()
+                ),
+            }
+        }
+        info!("═══════════════════════════════════════════════════");
+
+        let breached = results.iter().filter(|(_, _, breach)| breach.is_some()).count();
+        if breached > 0 {
+            return Err(ThresholdBreached { breached, checked: results.len() }.into());
+        }
+        Ok(())
+    }
+
+    async fn run(&self) -> Result<()> {
+        let markets = self.config.markets
+            .iter()
+            .map(|market| self.for_market(market))
+            .collect::<Result<Vec<_>>>()?;
+
+        info!("Starting Compound liquidity monitor for {} market(s)...", markets.len());
+        for market in &markets {
+            market.log_market_settings();
+        }
+        if let Some(ref url) = self.config.threshold_url {
+            info!("Threshold URL: {} (overrides liquidity_threshold when available)", url);
+        }
+        if let Some(ref expression) = self.config.alert_expression {
+            info!("Alert expression: {}", expression);
+        }
+        info!("Poll interval: {}s", self.config.poll_interval_secs);
+        if let Some(threshold) = self.config.health_threshold.filter(|_| !self.config.health_addresses.is_empty()) {
+            info!("Account health: {} account(s), alerting below a health ratio of {}", self.config.health_addresses.len(), threshold);
+        }
+        let transport = self.provider.as_ref().as_ref();
+        if transport.endpoint_count() > 1 {
+            info!("RPC endpoints: {} (using {})", transport.endpoint_count(), transport.active_url());
+        }
+        if let Some(ref url) = self.config.snapshot_sink_url {
+            info!("Snapshot sink: {}", url);
+        }
+        let history = match self.config.database_path {
+            Some(ref database) => Some(history::open(database.expose()).await?),
+            None => None,
+        };
+        if let Some(ref history) = history {
+            info!("History database: {}", history.location());
+        }
+        if self.config.startup_grace_secs > 0 {
+            info!("Startup grace period: {}s (alerts suppressed)", self.config.startup_grace_secs);
+        }
+
+        for market in &markets {
+            market.detect_capabilities().await?;
+        }
+
+        // With markets_per_tick below the market count, one timer reads the next
+        // few markets of the rotation each poll_interval_secs, so a market is read
+        // every `rounds` ticks
+        let rotation = self.config.markets_per_tick.filter(|&per_tick| per_tick < markets.len());
+        let rounds = rotation.map_or(1, |per_tick| markets.len().div_ceil(per_tick) as u64);
+        if let Some(per_tick) = rotation {
+            info!(
+                "Markets per tick: {} of {}, each market read every {} tick(s) (~{}s); per-market poll intervals are ignored",
+                per_tick, markets.len(), rounds, rounds * self.config.poll_interval_secs
+            );
+        }
+
+        let mut poll_intervals: Vec<u64> = markets.iter().map(CompoundMonitor::poll_interval_secs).collect();
+        // Shared with the /health handler, which allows two of the longest
+        // intervals between reads of a market
+        let longest_interval = |intervals: &[u64]| match rotation {
+            Some(_) => rounds * self.config.poll_interval_secs,
+            None => intervals.iter().copied().max().unwrap_or(self.config.poll_interval_secs),
+        };
+        let health_interval_secs = Arc::new(std::sync::atomic::AtomicU64::new(longest_interval(&poll_intervals)));
+
+        if let Some(port) = self.config.metrics_port {
+            let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await
+                .with_context(|| format!("Failed to listen on metrics_port {}", port))?;
+            info!("Metrics: http://0.0.0.0:{}/metrics", port);
+
+            let states = Arc::clone(&self.market_state);
+            let health = (self.config.health_port == Some(port))
+                .then(|| (Arc::clone(&self.last_successful_poll), Arc::clone(&health_interval_secs)));
+            tokio::spawn(server::serve(listener, move |path| match path {
+                "/metrics" => Some(server::Response::ok(
+                    "text/plain; version=0.0.4",
+                    render_metrics(&states.lock().unwrap()),
+                )),
+                "/health" => health.as_ref().map(|(last_poll, interval)| health_response(last_poll, interval)),
+                _ => None,
+            }));
+        }
+        if let Some(port) = self.config.health_port {
+            if self.config.metrics_port != Some(port) {
+                let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await
+                    .with_context(|| format!("Failed to listen on health_port {}", port))?;
+
+                let last_poll = Arc::clone(&self.last_successful_poll);
+                let interval = Arc::clone(&health_interval_secs);
+                tokio::spawn(server::serve(listener, move |path| match path {
+                    "/health" => Some(health_response(&last_poll, &interval)),
+                    _ => None,
+                }));
+            }
+            info!("Health: http://0.0.0.0:{}/health", port);
+        }
+
+        // Each market is polled on its own timer, the first time right away, or
+        // with a rotation every market on the one timer
+        let mut timers: Vec<tokio::time::Interval> = match rotation {
+            Some(_) => vec![poll_timer(tokio::time::Instant::now(), self.config.poll_interval_secs)],
+            None => poll_intervals.iter().map(|&secs| poll_timer(tokio::time::Instant::now(), secs)).collect(),
+        };
+        let mut reload_signal = ReloadSignal::new()?;
+        let mut shutdown_signal = ShutdownSignal::new()?;
+        let mut exports = Vec::new();
+
+        // With a WebSocket endpoint, poll on every new block instead of the timer
+        let block_provider = match self.config.rpc_urls.iter().find(|url| fallback::is_websocket(url.expose())) {
+            Some(url) => match Provider::<Ws>::connect(url.expose()).await {
+                Ok(provider) => Some(provider),
+                Err(e) => {
+                    warn!("Failed to connect to {} for block subscription, polling on each market's interval instead: {}",
+                        url, self.config.redact(&e.to_string()));
+                    None
+                }
+            },
+            None => None,
+        };
+        let mut blocks = match block_provider {
+            Some(ref provider) => match provider.subscribe_blocks().await {
+                Ok(stream) => {
+                    info!("Polling on every new block (WebSocket subscription)");
+                    Some(stream)
+                }
+                Err(e) => {
+                    warn!("Failed to subscribe to new blocks, polling on each market's interval instead: {}",
+                        self.config.redact(&e.to_string()));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Each market is polled independently as its timer fires, so a slow or
+        // retrying market doesn't hold up the others. At most
+        // max_concurrent_requests polls run at once; markets due beyond that wait
+        // in `queued`.
+        let concurrency = self.config.max_concurrent_requests.unwrap_or(10).max(1);
+        let start_poll = |index: usize| {
+            let market = &markets[index];
+            async move { (index, market.poll().await) }
+        };
+        let mut in_flight = FuturesUnordered::new();
+        let mut polling = vec![false; markets.len()];
+        let mut queued: VecDeque<usize> = VecDeque::new();
+        // Whether each market's latest poll succeeded; /health is only refreshed
+        // while all of them have
+        let mut healthy = vec![false; markets.len()];
+
+        loop {
+            tokio::select! {
+                index = next_tick(&mut timers), if blocks.is_none() => {
+                    let due = match rotation {
+                        Some(per_tick) => self.next_rotation(markets.len(), per_tick),
+                        None => vec![index],
+                    };
+                    for index in due {
+                        if polling[index] {
+                            debug!("{} is still being polled, skipping this tick", markets[index].config.market_address);
+                        } else {
+                            polling[index] = true;
+                            queued.push_back(index);
+                        }
+                    }
+                }
+                block = next_block(&mut blocks) => {
+                    match block {
+                        Some(block) => {
+                            debug!("New block {}", block.number.map_or_else(|| "pending".to_string(), |number| number.to_string()));
+                            // Blocks that arrived during the previous poll are stale
+                            if let Some(ref mut stream) = blocks {
+                                while let Some(Some(_)) = stream.next().now_or_never() {}
+                            }
+                            // Every new block is a reason to read every market (or the next
+                            // markets of the rotation) not already being read
+                            let due = match rotation {
+                                Some(per_tick) => self.next_rotation(markets.len(), per_tick),
+                                None => (0..markets.len()).collect(),
+                            };
+                            for index in due {
+                                if !polling[index] {
+                                    polling[index] = true;
+                                    queued.push_back(index);
+                                }
+                            }
+                        }
+                        None => {
+                            warn!("Block subscription ended, polling on each market's interval instead");
+                            blocks = None;
+                            timers.iter_mut().for_each(tokio::time::Interval::reset);
+                        }
+                    }
+                }
+                Some((index, entry)) = in_flight.next() => {
+                    let entry: Option<SnapshotEntry> = entry;
+                    polling[index] = false;
+                    healthy[index] = entry.is_some();
+                    if healthy.iter().all(|&healthy| healthy) {
+                        *self.last_successful_poll.lock().unwrap() = Some(Instant::now());
+                    }
+                    let entries: Vec<SnapshotEntry> = entry.into_iter().collect();
+                    if let Some(ref history) = history {
+                        record_readings(history.as_ref(), &entries).await;
+                    }
+                    exports.retain(|export: &tokio::task::JoinHandle<()>| !export.is_finished());
+                    exports.extend(self.export_snapshots(entries));
+                }
+                signal = shutdown_signal.recv() => {
+                    info!("{} received", signal);
+                    break;
+                }
+                _ = reload_signal.recv() => {
+                    match self.reload(&markets, &poll_intervals) {
+                        Ok(reloaded) if rotation.is_some() => poll_intervals = reloaded,
+                        Ok(reloaded) => {
+                            for ((timer, current), reloaded) in timers.iter_mut().zip(&mut poll_intervals).zip(reloaded) {
+                                if reloaded != *current {
+                                    *current = reloaded;
+                                    *timer = poll_timer(tokio::time::Instant::now() + Duration::from_secs(reloaded), reloaded);
+                                }
+                            }
+                            health_interval_secs.store(longest_interval(&poll_intervals), std::sync::atomic::Ordering::Relaxed);
+                        }
+                        Err(e) => error!("Failed to reload configuration, keeping current settings: {:#}", e),
+                    }
+                }
+            }
+
+            while in_flight.len() < concurrency {
+                let Some(index) = queued.pop_front() else { break };
+                in_flight.push(start_poll(index));
+            }
+        }
+
+        // Polls in progress always finish and deliver their alerts; queued ones
+        // are dropped
+        if !in_flight.is_empty() {
+            info!("Waiting for {} poll(s) in progress to finish", in_flight.len());
+            while let Some((_, entry)) = in_flight.next().await {
+                let entries: Vec<SnapshotEntry> = entry.into_iter().collect();
+                if let Some(ref history) = history {
+                    record_readings(history.as_ref(), &entries).await;
+                }
+                exports.extend(self.export_snapshots(entries));
+            }
+        }
+
+        info!("Shutting down gracefully");
+        exports.retain(|export| !export.is_finished());
+        if !exports.is_empty() {
+            info!("Waiting up to {}s for {} snapshot export(s) to finish", SHUTDOWN_TIMEOUT.as_secs(), exports.len());
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, futures::future::join_all(exports)).await.is_err() {
+                warn!("Snapshot exports still running after {}s, abandoning them", SHUTDOWN_TIMEOUT.as_secs());
+            }
+        }
+        info!("Monitor stopped");
+        Ok(())
+    }
+}
+
+/// 200 if every market was read within the last two poll intervals, else 503
+fn health_response(last_poll: &Mutex<Option<Instant>>, interval_secs: &std::sync::atomic::AtomicU64) -> server::Response {
+    let max_age = Duration::from_secs(2 * interval_secs.load(std::sync::atomic::Ordering::Relaxed));
+    let age = last_poll.lock().unwrap().map(|last_poll| last_poll.elapsed());
+
+    let healthy = age.is_some_and(|age| age <= max_age);
+    let body = serde_json::json!({
+        "status": if healthy { "ok" } else { "unhealthy" },
+        "last_successful_poll_secs_ago": age.map(|age| age.as_secs()),
+        "max_age_secs": max_age.as_secs(),
+    });
+    server::Response {
+        status: if healthy { 200 } else { 503 },
+        content_type: "application/json",
+        body: format!("{}\n", body),
+    }
+}
+
+/// A market's poll timer, first firing at `start`. A poll that overruns the
+/// interval delays the next one rather than causing a burst.
+fn poll_timer(start: tokio::time::Instant, secs: u64) -> tokio::time::Interval {
+    let mut timer = tokio::time::interval_at(start, Duration::from_secs(secs));
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    timer
+}
+
+/// Index of the first poll timer to fire
+async fn next_tick(timers: &mut [tokio::time::Interval]) -> usize {
+    if timers.is_empty() {
+        return std::future::pending().await;
+    }
+    let ticks = timers.iter_mut().map(|timer| Box::pin(timer.tick()));
+    futures::future::select_all(ticks).await.1
+}
 
-        // Get base token address
-        let base_token_address = contract.base_token().call().await?;
+/// The next block from a subscription, or never without one
+async fn next_block<S: futures::Stream + Unpin>(blocks: &mut Option<S>) -> Option<S::Item> {
+    match blocks {
+        Some(stream) => stream.next().await,
+        None => std::future::pending().await,
+    }
+}
 
-        // Withdraw from Compound
-        info!("Sending withdraw transaction...");
-        let withdraw_tx = contract.withdraw(base_token_address, amount);
-        let pending_tx = withdraw_tx.send().await?;
-        let receipt = pending_tx.await?.context("Withdraw transaction failed")?;
+/// Append a poll's readings to the history database, logging any failure
+async fn record_readings(history: &dyn History, entries: &[SnapshotEntry]) {
+    let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    for entry in entries {
+        let row = HistoryRow {
+            timestamp: timestamp.clone(),
+            market: entry.market_address.clone(),
+            symbol: entry.market_symbol.clone(),
+            available_liquidity: entry.available_liquidity.clone(),
+            total_borrows: entry.total_borrows.clone(),
+            total_reserves: entry.total_reserves.clone(),
+            utilization: entry.utilization,
+            supply_apy: entry.supply_apy,
+            borrow_apy: entry.borrow_apy,
+        };
+        if let Err(e) = history.append(&row).await {
+            error!("Failed to record reading for {}: {:#}", entry.market_address, e);
+        }
+    }
+}
 
-        info!("✓ Withdraw successful!");
-        info!("Transaction hash: {:?}", receipt.transaction_hash);
-        info!("Gas used: {:?}", receipt.gas_used);
+/// Print the last `limit` readings from the history database, returning how
+/// many were printed
+async fn print_history(history: &dyn History, limit: usize, market: Option<&str>) -> Result<usize> {
+    let rows = history.latest(limit, market).await?;
+    let percent = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |value| format!("{:.2}%", value));
 
-        Ok(())
+    info!("═══════════════════════════════════════════════════");
+    info!("History: {} ({} reading(s))", history.location(), rows.len());
+    info!("═══════════════════════════════════════════════════");
+    for row in &rows {
+        info!(
+            "{} {} ({}) | Liquidity: {} | Borrows: {} | Reserves: {} | Utilization: {} | Supply APY: {} | Borrow APY: {}",
+            row.timestamp, row.symbol, row.market, row.available_liquidity, row.total_borrows, row.total_reserves,
+            percent(row.utilization), percent(row.supply_apy), percent(row.borrow_apy)
+        );
     }
+    info!("═══════════════════════════════════════════════════");
 
-    async fn check_balance(&self, address: &str, name: Option<&str>) -> Result<()> {
-        let address: H160 = address.parse().context("Invalid address")?;
-        let market_address: H160 = self.config.market_address.parse()?;
-
-        let contract = Comet::new(market_address, Arc::clone(&self.provider));
+    Ok(rows.len())
+}
 
-        // Get base token address
-        let base_token_address = contract.base_token().call().await?;
-        let base_token = ERC20::new(base_token_address, Arc::clone(&self.provider));
+/// Turn a failed contract call into an error, decoding the revert reason when
+/// the node returned one
+fn contract_error<M: Middleware + 'static>(e: ContractError<M>) -> anyhow::Error {
+    let revert_data = e.as_revert().cloned().or_else(|| {
+        e.as_middleware_error()?.as_error_response()?.as_revert_data()
+    });
+    match revert_data {
+        Some(data) => anyhow::anyhow!("Transaction would revert: {}", revert::decode(&data)),
+        None => e.into(),
+    }
+}
 
-        // Get token info
-        let symbol = base_token.symbol().call().await?;
-        let decimals = base_token.decimals().call().await?;
+/// Total of the ERC20 `token` transfers out of `from` in a receipt
+fn transferred_amount(receipt: &TransactionReceipt, token: H160, from: H160) -> Option<U256> {
+    let transfer_topic = H256::from(ethers::utils::keccak256("Transfer(address,address,uint256)"));
+    receipt.logs
+        .iter()
+        .filter(|log| {
+            log.address == token
+                && log.topics.len() == 3
+                && log.topics[0] == transfer_topic
+                && log.topics[1] == H256::from(from)
+        })
+        .map(|log| U256::from_big_endian(&log.data))
+        .reduce(|total, amount| total.saturating_add(amount))
+}
 
-        // Check wallet balance
-        let wallet_balance = base_token.balance_of(address).call().await?;
+/// Ask a question on the terminal and return the lowercased first character of
+/// the answer ('n' for an empty answer)
+fn confirm(prompt: &str) -> Result<char> {
+    use std::io::Write;
 
-        // Check Compound balance
-        let compound_balance = contract.balance_of(address).call().await?;
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
 
-        // Format balances for display
-        let divisor = U256::from(10u128.pow(decimals as u32));
-        let wallet_formatted = self.format_balance(wallet_balance, divisor);
-        let compound_formatted = self.format_balance(compound_balance, divisor);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)
+        .context("Failed to read confirmation")?;
+    Ok(answer.trim().chars().next().map_or('n', |c| c.to_ascii_lowercase()))
+}
 
-        info!("═══════════════════════════════════════════════════");
-        if let Some(name) = name {
-            info!("Name: {}", name);
+/// The address given with --address, otherwise the signer of --private-key, of
+/// COMPOUND_PRIVATE_KEY, of keystore_path or of the private_key in config.json
+fn resolve_owner(address: Option<String>, private_key: Option<Secret>, config: &Config) -> Result<H160> {
+    if let Some(address) = address {
+        return address.parse().context("Invalid address");
+    }
+    if config.signer == SignerKind::Ledger && private_key.is_none() {
+        anyhow::bail!("With signer \"ledger\", give the account to check with --address");
+    }
+    let private_key = private_key.or_else(|| config.env_private_key.clone());
+    if private_key.is_none() {
+        if let Some(address) = config.keystore_address()? {
+            return Ok(address);
         }
-        info!("Address: {}", address);
-        info!("Token: {} (base token: {})", symbol, base_token_address);
-        info!("Decimals: {}", decimals);
-        info!("───────────────────────────────────────────────────");
-        info!("Wallet balance:   {} {} ({})", wallet_formatted, symbol, wallet_balance);
-        info!("Compound balance: {} {} ({})", compound_formatted, symbol, compound_balance);
-        info!("═══════════════════════════════════════════════════");
-
-        Ok(())
     }
 
-    async fn check_balance_batch(&self) -> Result<()> {
-        let address_config = MonitorAddressConfig::load()?;
+    let key = private_key
+        .or_else(|| config.private_key.clone())
+        .context("No owner given. Use --address, --private-key, COMPOUND_PRIVATE_KEY, or add 'keystore_path' or 'private_key' to config.json")?;
+    Ok(key.expose().parse::<LocalWallet>()
+        .context("Invalid private key")?
+        .address())
+}
 
-        if address_config.addresses.is_empty() {
-            info!("No addresses found in monitor_address.json");
-            return Ok(());
+/// The Ledger with signer "ledger", otherwise the key given with --private-key
+/// or the configured signing key
+async fn resolve_signer(private_key: Option<Secret>, config: &Config) -> Result<TxSigner> {
+    if config.signer == SignerKind::Ledger {
+        if private_key.is_some() {
+            anyhow::bail!("--private-key can't be used with signer \"ledger\" in config.json");
         }
+        let path = match config.ledger_derivation_path {
+            Some(ref path) => HDPath::Other(path.clone()),
+            None => HDPath::LedgerLive(config.ledger_index.unwrap_or(0)),
+        };
+        return TxSigner::connect_ledger(path).await;
+    }
 
-        info!("Checking balances for {} addresses...", address_config.addresses.len());
-        info!("");
+    let key = match private_key {
+        Some(key) => key,
+        None => config.signing_key()?
+            .context("Private key not provided. Use --private-key, set COMPOUND_PRIVATE_KEY, or add 'keystore_path' or 'private_key' to config.json")?,
+    };
+    TxSigner::from_private_key(key.expose())
+}
 
-        for monitor_addr in &address_config.addresses {
-            match self.check_balance(&monitor_addr.address, Some(&monitor_addr.name)).await {
-                Ok(_) => info!(""),
-                Err(e) => {
-                    error!("Failed to check balance for {} ({}): {}",
-                        monitor_addr.name, monitor_addr.address, e);
-                    info!("");
-                }
-            }
-        }
+#[tokio::main]
+async fn main() -> Result<std::process::ExitCode> {
+    let cli = Cli::parse();
 
-        Ok(())
+    // Keep stdout for the JSON output of --json commands
+    let writer = if matches!(cli.command, Some(Commands::Balance { json: true, .. })) {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let filter = tracing_subscriber::EnvFilter::from_default_env()
+        .add_directive(tracing::Level::INFO.into());
+    match LogFormat::from_config_file(&cli.config) {
+        LogFormat::Text => tracing_subscriber::fmt().with_writer(writer).with_env_filter(filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt().json().with_writer(writer).with_env_filter(filter).init(),
     }
 
-    fn format_balance(&self, balance: U256, divisor: U256) -> String {
-        if divisor.is_zero() {
-            return balance.to_string();
-        }
+    let summary_file = cli.summary_file.clone();
+    let command = command_name(cli.command.as_ref());
 
-        let whole = balance / divisor;
-        let remainder = balance % divisor;
+    let loaded = Config::load(&cli.config)
+        .and_then(|config| config.validate().map(|_| config))
+        .map(|config| Config { monitor_addresses_path: cli.monitor_addresses.clone(), ..config });
+    let (config, outcome) = match loaded.context("Failed to load configuration") {
+        Ok(config) => {
+            let outcome = execute(cli, config.clone()).await;
+            (Some(config), outcome)
+        }
+        Err(e) => (None, Err(e)),
+    };
 
-        if remainder.is_zero() {
-            format!("{}", whole)
-        } else {
-            // Calculate decimal part
-            let decimals_str = format!("{:0width$}", remainder, width = divisor.to_string().len() - 1);
-            let trimmed = decimals_str.trim_end_matches('0');
-            if trimmed.is_empty() {
-                format!("{}", whole)
-            } else {
-                format!("{}.{}", whole, trimmed)
-            }
+    if let Some(path) = summary_file {
+        // Errors can embed the RPC URL; only the loaded config knows what to redact
+        let error = outcome.as_ref().err().map(|e| {
+            let message = format!("{:#}", e);
+            config.as_ref().map_or(message.clone(), |config| config.redact(&message))
+        });
+        if let Err(e) = write_summary(&path, command, outcome.as_ref().ok(), error) {
+            error!("Failed to write summary to {}: {:#}", path, e);
         }
     }
 
-    fn calculate_apy(&self, rate_per_second: u64) -> f64 {
-        // Compound V3 rates are per second with scaling factor
-        // rate_per_second is in units where 1e18 = 100% per second
-        // APY = (1 + rate_per_second)^(seconds_per_year) - 1
-
-        const SECONDS_PER_YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0; // 31,557,600
-        const SCALE: f64 = 1e18;
+    match outcome {
+        Ok(_) => Ok(std::process::ExitCode::SUCCESS),
+        Err(e) if e.is::<ThresholdBreached>() => {
+            error!("{}", e);
+            Ok(std::process::ExitCode::from(2))
+        }
+        Err(e) => Err(e),
+    }
+}
 
-        let rate = rate_per_second as f64 / SCALE;
-        let apy = ((1.0 + rate).powf(SECONDS_PER_YEAR) - 1.0) * 100.0;
+/// Returned by `check` when a market is in breach, so the process exits with
+/// code 2 rather than the generic error code 1
+#[derive(Debug)]
+struct ThresholdBreached {
+    breached: usize,
+    checked: usize,
+}
 
-        apy
+impl std::fmt::Display for ThresholdBreached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} market(s) below threshold", self.breached, self.checked)
     }
+}
 
-    async fn run(&self) -> Result<()> {
-        let version_str = match self.config.compound_version {
-            CompoundVersion::V2 => "V2",
-            CompoundVersion::V3 => "V3 (Comet)",
-        };
+impl std::error::Error for ThresholdBreached {}
 
-        info!("Starting Compound {} liquidity monitor...", version_str);
-        if let Some(ref name) = self.config.market_name {
-            info!("Market: {} ({})", name, self.config.market_address);
-        } else {
-            info!("Market: {}", self.config.market_address);
-        }
-        info!("Threshold: {}", self.config.liquidity_threshold);
-        info!("Poll interval: {}s", self.config.poll_interval_secs);
-        info!("Notifications: {}", if self.config.notification_enabled.unwrap_or(true) { "enabled" } else { "disabled" });
+/// Print `value` as pretty JSON on stdout, for commands with --json output
+fn print_json(value: &serde_json::Value) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).context("Failed to serialize JSON output")?;
+    println!("{}", json);
+    Ok(())
+}
 
-        let mut interval = tokio::time::interval(
-            Duration::from_secs(self.config.poll_interval_secs)
-        );
+/// Name of the command as given on the command line
+fn command_name(command: Option<&Commands>) -> &'static str {
+    match command {
+        Some(Commands::Monitor) | None => "monitor",
+        Some(Commands::Check) => "check",
+        Some(Commands::Supply { .. }) => "supply",
+        Some(Commands::Withdraw { .. }) => "withdraw",
+        Some(Commands::Borrow { .. }) => "borrow",
+        Some(Commands::Balance { .. }) => "balance",
+        Some(Commands::Allowance { .. }) => "allowance",
+        Some(Commands::HealthCheck { .. }) => "health-check",
+        Some(Commands::Accounting { .. }) => "accounting",
+        Some(Commands::BatchTx { .. }) => "batch-tx",
+        Some(Commands::Backtest { .. }) => "backtest",
+        Some(Commands::Scan { .. }) => "scan",
+        Some(Commands::Discover) => "discover",
+        Some(Commands::History { .. }) => "history",
+        Some(Commands::TestWebhook) => "test-webhook",
+        Some(Commands::TestAlert) => "test-alert",
+        Some(Commands::ShowConfig) => "show-config",
+    }
+}
 
-        loop {
-            interval.tick().await;
+/// Machine-readable outcome of a command, written by --summary-file
+#[derive(Debug, Serialize)]
+struct CommandSummary<'a> {
+    command: &'a str,
+    /// "ok" or "error"
+    status: &'a str,
+    /// Key values of the outcome, when the command produces any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    timestamp: String,
+}
 
-            match self.check_liquidity().await {
-                Ok((liquidity, borrows, reserves, symbol)) => {
-                    if liquidity < self.threshold {
-                        warn!(
-                            "Liquidity below threshold! Current: {}, Threshold: {}",
-                            liquidity, self.threshold
-                        );
+fn write_summary(
+    path: &str,
+    command: &str,
+    result: Option<&Option<serde_json::Value>>,
+    error: Option<String>,
+) -> Result<()> {
+    let summary = CommandSummary {
+        command,
+        status: if error.is_none() { "ok" } else { "error" },
+        result: result.and_then(Option::as_ref),
+        error,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+    let line = serde_json::to_string(&summary)?;
 
-                        let alert = LiquidityAlert {
-                            market_address: self.config.market_address.clone(),
-                            market_symbol: symbol,
-                            available_liquidity: liquidity.to_string(),
-                            total_borrows: borrows.to_string(),
-                            total_reserves: reserves.to_string(),
-                            threshold: self.threshold.to_string(),
-                            timestamp: chrono::Utc::now().timestamp(),
-                            message: format!(
-                                "Available liquidity ({}) is below threshold ({})",
-                                liquidity, self.threshold
-                            ),
-                        };
-
-                        // Only send alert if notifications are enabled
-                        if self.config.notification_enabled.unwrap_or(true) {
-                            if let Err(e) = self.send_alert(alert).await {
-                                error!("Failed to send alert: {}", e);
-                            }
-                        } else {
-                            info!("Notification disabled, skipping alert");
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to check liquidity: {}", e);
-                }
-            }
-        }
+    if path == "-" {
+        eprintln!("{}", line);
+        return Ok(());
     }
+    fs::write(path, format!("{}\n", line))
+        .with_context(|| format!("Failed to write {}", path))
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into())
-        )
-        .init();
+/// Run the command, returning its key values for --summary-file
+async fn execute(cli: Cli, mut config: Config) -> Result<Option<serde_json::Value>> {
+    if let Some(ref market) = cli.market {
+        config.select_market(market)?;
+    }
 
-    let cli = Cli::parse();
+    if let Some(Commands::ShowConfig) = cli.command {
+        // Secret fields serialize in redacted form
+        println!("{}", serde_json::to_string_pretty(&config)?);
+        return Ok(None);
+    }
 
-    let config = Config::load()
-        .context("Failed to load configuration")?;
+    // Reads only the local database, no RPC needed
+    if let Some(Commands::History { limit }) = cli.command {
+        let database = config.database_path.as_ref()
+            .context("database_path is not set in config.json")?;
+        let market = cli.market.is_some().then_some(config.market_address.as_str());
+        let count = print_history(history::open(database.expose()).await?.as_ref(), limit, market).await?;
+        return Ok(Some(serde_json::json!({ "readings": count })));
+    }
 
     let monitor = CompoundMonitor::new(config.clone()).await?;
 
-    match cli.command {
-        Some(Commands::Supply { amount, private_key }) => {
+    let result = match cli.command {
+        Some(Commands::Supply { amount, private_key, force, idempotency_key, dry_run, yes, nonce }) => {
             let amount = U256::from_dec_str(&amount)
                 .context("Invalid amount")?;
 
-            if monitor.config.compound_version != CompoundVersion::V3 {
-                anyhow::bail!("Supply/withdraw is only supported for Compound V3. Set 'compound_version': 'v3' in config.json");
-            }
-
-            // Use CLI private key if provided, otherwise use config
-            let key = private_key
-                .or_else(|| monitor.config.private_key.clone())
-                .context("Private key not provided. Use --private-key or add 'private_key' to config.json")?;
+            // Use CLI private key if provided, otherwise the configured signer
+            let signer = resolve_signer(private_key, &monitor.config).await?;
 
-            monitor.supply_v3(amount, &key).await?;
+            let options = TxOptions { force, idempotency_key, to: None, dry_run, yes, nonce: nonce.map(U256::from) };
+            let tx_hash = match monitor.config.compound_version {
+                CompoundVersion::V2 => monitor.supply_v2(amount, &signer, &options).await?,
+                CompoundVersion::V3 => monitor.supply_v3(amount, &signer, &options).await?,
+            };
+            Some(serde_json::json!({
+                "market": monitor.config.market_address,
+                "amount": amount.to_string(),
+                "tx_hash": tx_hash.map(|hash| format!("{:?}", hash)),
+                "dry_run": dry_run,
+            }))
         }
-        Some(Commands::Withdraw { amount, private_key }) => {
-            let amount = U256::from_dec_str(&amount)
-                .context("Invalid amount")?;
+        Some(Commands::Withdraw { amount, private_key, force, idempotency_key, dry_run, yes, nonce }) => {
+            let amount = if amount.eq_ignore_ascii_case("max") {
+                if monitor.config.compound_version != CompoundVersion::V3 {
+                    anyhow::bail!("--amount max is only supported for Compound V3");
+                }
+                U256::MAX
+            } else {
+                U256::from_dec_str(&amount).context("Invalid amount")?
+            };
 
+            // Use CLI private key if provided, otherwise the configured signer
+            let signer = resolve_signer(private_key, &monitor.config).await?;
+
+            let options = TxOptions { force, idempotency_key, to: None, dry_run, yes, nonce: nonce.map(U256::from) };
+            let tx_hash = match monitor.config.compound_version {
+                CompoundVersion::V2 => monitor.withdraw_v2(amount, &signer, &options).await?,
+                CompoundVersion::V3 => monitor.withdraw_v3(amount, &signer, &options).await?,
+            };
+            Some(serde_json::json!({
+                "market": monitor.config.market_address,
+                "amount": if amount == U256::MAX { "max".to_string() } else { amount.to_string() },
+                "tx_hash": tx_hash.map(|hash| format!("{:?}", hash)),
+                "dry_run": dry_run,
+            }))
+        }
+        Some(Commands::Borrow { amount, private_key, force, idempotency_key, dry_run }) => {
             if monitor.config.compound_version != CompoundVersion::V3 {
-                anyhow::bail!("Supply/withdraw is only supported for Compound V3. Set 'compound_version': 'v3' in config.json");
+                anyhow::bail!("Borrow is only supported for Compound V3. Set 'compound_version': 'v3' in config.json");
             }
 
-            // Use CLI private key if provided, otherwise use config
-            let key = private_key
-                .or_else(|| monitor.config.private_key.clone())
-                .context("Private key not provided. Use --private-key or add 'private_key' to config.json")?;
+            let amount = U256::from_dec_str(&amount)
+                .context("Invalid amount")?;
+            let signer = resolve_signer(private_key, &monitor.config).await?;
 
-            monitor.withdraw_v3(amount, &key).await?;
+            let options = TxOptions { force, idempotency_key, to: None, dry_run, yes: false, nonce: None };
+            let tx_hash = monitor.borrow_v3(amount, &signer, &options).await?;
+            Some(serde_json::json!({
+                "market": monitor.config.market_address,
+                "amount": amount.to_string(),
+                "tx_hash": tx_hash.map(|hash| format!("{:?}", hash)),
+                "dry_run": dry_run,
+            }))
         }
-        Some(Commands::Balance { address }) => {
-            if let Some(addr) = address {
+        Some(Commands::Balance { address, all_markets, json, summary }) => {
+            if summary && (all_markets || address.is_some()) {
+                anyhow::bail!("--summary totals the addresses in {}; omit --address and --all-markets", config.monitor_addresses_path);
+            }
+            if all_markets {
+                if address.is_some() {
+                    anyhow::bail!("--all-markets checks the addresses in {}; omit --address", config.monitor_addresses_path);
+                }
+                if json {
+                    anyhow::bail!("--json is not supported with --all-markets");
+                }
+                monitor.check_balance_all_markets().await?;
+                None
+            } else if let Some(addr) = address {
                 // Check single address from command line
-                monitor.check_balance(&addr, None).await?;
+                let reading = monitor.check_balance(&addr, None, json).await?;
+                Some(serde_json::json!({
+                    "market": monitor.config.market_address,
+                    "address": addr,
+                    "symbol": reading.symbol,
+                    "decimals": reading.decimals,
+                    "wallet_balance": reading.wallet_balance.to_string(),
+                    "compound_balance": reading.compound_balance.to_string(),
+                    "rewards_owed": reading.rewards.as_ref().map(|rewards| rewards.owed.to_string()),
+                    "rewards_symbol": reading.rewards.as_ref().map(|rewards| rewards.symbol.clone()),
+                }))
             } else {
                 // Check all addresses from monitor_address.json
-                monitor.check_balance_batch().await?;
+                let (checked, failed) = monitor.check_balance_batch(json, summary).await?;
+                Some(serde_json::json!({ "checked": checked, "failed": failed }))
+            }
+        }
+        Some(Commands::Allowance { address, private_key }) => {
+            if monitor.config.compound_version != CompoundVersion::V3 {
+                anyhow::bail!("Allowance is only supported for Compound V3. Set 'compound_version': 'v3' in config.json");
             }
+
+            let owner = resolve_owner(address, private_key, &monitor.config)?;
+            monitor.check_allowance(owner).await?;
+            None
+        }
+        Some(Commands::HealthCheck { address, private_key }) => {
+            if monitor.config.compound_version != CompoundVersion::V3 {
+                anyhow::bail!("HealthCheck is only supported for Compound V3. Set 'compound_version': 'v3' in config.json");
+            }
+
+            let account = resolve_owner(address, private_key, &monitor.config)?;
+            let position = monitor.health_check(account).await?;
+            Some(serde_json::json!({
+                "market": monitor.config.market_address,
+                "address": format!("{:?}", account),
+                "debt_usd": position.debt_usd,
+                "borrow_limit_usd": position.borrow_limit_usd,
+                "liquidation_limit_usd": position.liquidation_limit_usd,
+                "health_ratio": position.health_ratio(),
+            }))
+        }
+        Some(Commands::Accounting { address, private_key }) => {
+            if monitor.config.compound_version != CompoundVersion::V3 {
+                anyhow::bail!("Accounting is only supported for Compound V3. Set 'compound_version': 'v3' in config.json");
+            }
+
+            let owner = resolve_owner(address, private_key, &monitor.config)?;
+            monitor.accounting(owner).await?;
+            None
+        }
+        Some(Commands::BatchTx { file, private_key, yes, continue_on_error, force }) => {
+            let signer = resolve_signer(private_key, &monitor.config).await?;
+
+            monitor.batch_tx(&file, &signer, yes, continue_on_error, force).await?;
+            None
+        }
+        Some(Commands::Backtest { input }) => {
+            monitor.backtest(&input)?;
+            None
+        }
+        Some(Commands::Scan { input, output }) => {
+            monitor.scan_markets(&input, &output).await?;
+            None
+        }
+        Some(Commands::Discover) => {
+            let (chain_id, markets) = monitor.discover().await?;
+            Some(serde_json::json!({ "chain_id": chain_id, "markets": markets }))
+        }
+        Some(Commands::TestWebhook) => {
+            monitor.test_webhook().await?;
+            None
+        }
+        Some(Commands::TestAlert) => {
+            monitor.test_alert().await?;
+            None
         }
+        Some(Commands::Check) => {
+            monitor.check().await?;
+            None
+        }
+        Some(Commands::ShowConfig) | Some(Commands::History { .. }) => unreachable!("handled before connecting"),
         Some(Commands::Monitor) | None => {
             // Default: run monitor
             monitor.run().await?;
+            None
         }
+    };
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentage_handles_near_max_values() {
+        assert_eq!(percentage(U256::MAX, U256::MAX), Some(100.0));
+        // Truncated to ten decimal places rather than rounded up to 100
+        assert_eq!(percentage(U256::MAX - 1, U256::MAX), Some(99.9999999999));
+        assert_eq!(percentage(U256::MAX, U256::one()), Some(U256::MAX.to_string().parse::<f64>().unwrap() * 100.0));
+        assert_eq!(percentage(U256::one(), U256::MAX), Some(0.0));
     }
 
-    Ok(())
+    #[test]
+    fn percentage_of_zero_denominator_is_none() {
+        assert_eq!(percentage(U256::zero(), U256::zero()), None);
+        assert_eq!(percentage(U256::MAX, U256::zero()), None);
+    }
+
+    #[test]
+    fn percentage_exact_and_tiny_ratios() {
+        assert_eq!(percentage(U256::from(5), U256::from(5)), Some(100.0));
+        assert_eq!(percentage(U256::from(1), U256::from(4)), Some(25.0));
+        assert_eq!(percentage(U256::zero(), U256::from(4)), Some(0.0));
+        // One part in a million is within the twelve digits kept
+        assert_eq!(percentage(U256::one(), U256::exp10(6)), Some(0.0001));
+    }
+
+    #[test]
+    fn mul_div_widens_the_product() {
+        let e18 = U256::exp10(18);
+        assert_eq!(mul_div(U256::MAX, e18, e18), Some(U256::MAX));
+        assert_eq!(mul_div(U256::MAX, U256::MAX, U256::MAX), Some(U256::MAX));
+        assert_eq!(mul_div(U256::MAX, U256::from(2), U256::one()), None);
+        assert_eq!(mul_div(U256::MAX, U256::one(), U256::zero()), None);
+        // 1e8 cToken units at an exchange rate of 0.02 underlying units each
+        assert_eq!(mul_div(U256::exp10(8), U256::from(2) * U256::exp10(16), e18), Some(U256::from(2_000_000)));
+    }
+
+    fn entry_with_reserves(total_reserves: &str) -> SnapshotEntry {
+        SnapshotEntry {
+            market_address: "0xc3d688B66703497DAA19211EEdff47f25384cdc3".to_string(),
+            market_symbol: "cUSDCv3".to_string(),
+            available_liquidity: "1000".to_string(),
+            total_borrows: "500".to_string(),
+            total_reserves: total_reserves.to_string(),
+            total_supply: None,
+            utilization: None,
+            supply_apy: None,
+            borrow_apy: None,
+            base_price_usd: None,
+            available_liquidity_usd: None,
+            timestamp: AlertTimestamp::Unix(0),
+        }
+    }
+
+    #[test]
+    fn to_snapshot_keeps_reserves_at_the_i256_bounds() {
+        let past_u128 = I256::from_raw(U256::from(u128::MAX) + 1);
+        for reserves in [I256::MIN, I256::minus_one(), I256::zero(), I256::MAX, past_u128] {
+            let snapshot = entry_with_reserves(&reserves.to_string()).to_snapshot().unwrap();
+            assert_eq!(snapshot.reserves, reserves);
+        }
+    }
+
+    #[test]
+    fn to_snapshot_rejects_reserves_outside_i256() {
+        let past_max = (I256::MAX.into_raw() + 1).to_string();
+        assert!(entry_with_reserves(&past_max).to_snapshot().is_err());
+        assert!(entry_with_reserves(&format!("-{}", past_max)).to_snapshot().is_ok());
+        assert!(entry_with_reserves(&format!("-{}1", past_max)).to_snapshot().is_err());
+    }
+
+    #[test]
+    fn signed_reserves_clamps_past_i256_max() {
+        let i256_max = I256::MAX.into_raw();
+        assert_eq!(signed_reserves(U256::zero()), I256::zero());
+        assert_eq!(signed_reserves(U256::from(u128::MAX) + 1), I256::from_raw(U256::from(u128::MAX) + 1));
+        assert_eq!(signed_reserves(i256_max), I256::MAX);
+        assert_eq!(signed_reserves(i256_max + 1), I256::MAX);
+        assert_eq!(signed_reserves(U256::MAX), I256::MAX);
+    }
+
+    #[test]
+    fn i256_to_f64_keeps_the_sign() {
+        assert_eq!(i256_to_f64(I256::zero()), 0.0);
+        assert_eq!(i256_to_f64(I256::minus_one()), -1.0);
+        assert_eq!(i256_to_f64(I256::from_raw(U256::from(u128::MAX) + 1)), 2f64.powi(128));
+        assert_eq!(i256_to_f64(I256::MAX), 2f64.powi(255));
+        assert_eq!(i256_to_f64(I256::MIN), -(2f64.powi(255)));
+    }
+
+    /// Write `content` to a temporary config file and load it
+    fn load_config(name: &str, content: &str) -> Config {
+        let path = std::env::temp_dir().join(format!("compound-monitor-{}-{}.json", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        let config = Config::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        config.unwrap()
+    }
+
+    fn market<'a>(config: &'a Config, address: &str) -> &'a MarketConfig {
+        config.markets.iter().find(|market| market.address == address).unwrap()
+    }
+
+    const MERGE_CONFIG: &str = r#"{
+        "rpc_url": "http://127.0.0.1:1",
+        "webhook_url": "https://top.example/notify",
+        "poll_interval_secs": 60,
+        "liquidity_threshold": "100",
+        "notification_enabled": true,
+        "alert_cooldown_secs": 300,
+        "defaults": {
+            "liquidity_threshold": "200",
+            "notification_enabled": false,
+            "alert_cooldown_secs": 600
+        },
+        "markets": [
+            {
+                "address": "0x0000000000000000000000000000000000000001",
+                "liquidity_threshold": "300",
+                "notification_enabled": true,
+                "webhook_url": "https://market.example/notify",
+                "alert_cooldown_secs": 900,
+                "poll_interval_secs": 15
+            },
+            {
+                "address": "0x0000000000000000000000000000000000000002"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn market_settings_override_defaults_and_top_level() {
+        let config = load_config("market-override", MERGE_CONFIG);
+        let market = market(&config, "0x0000000000000000000000000000000000000001");
+        assert_eq!(market.alert_cooldown_secs, Some(900));
+        assert_eq!(market.notification_enabled, Some(true));
+        assert_eq!(market.webhook_url.as_ref().unwrap().expose(), "https://market.example/notify");
+        assert_eq!(market.liquidity_threshold.as_ref().unwrap().default_value(), "300");
+        assert_eq!(market.poll_interval_secs, Some(15));
+    }
+
+    #[test]
+    fn unset_market_settings_fall_back_to_defaults_then_top_level() {
+        let config = load_config("market-unset", MERGE_CONFIG);
+        let market = market(&config, "0x0000000000000000000000000000000000000002");
+        // Set in defaults
+        assert_eq!(market.alert_cooldown_secs, Some(600));
+        assert_eq!(market.notification_enabled, Some(false));
+        assert_eq!(market.liquidity_threshold.as_ref().unwrap().default_value(), "200");
+        // Only set at the top level
+        assert_eq!(market.webhook_url.as_ref().unwrap().expose(), "https://top.example/notify");
+        assert_eq!(market.poll_interval_secs, Some(60));
+    }
+
+    #[test]
+    fn defaults_apply_when_the_top_level_is_unset() {
+        let config = load_config("defaults-only", r#"{
+            "rpc_url": "http://127.0.0.1:1",
+            "poll_interval_secs": 60,
+            "defaults": {
+                "liquidity_threshold": "200",
+                "notification_enabled": false,
+                "webhook_url": "https://defaults.example/notify",
+                "alert_cooldown_secs": 600,
+                "poll_interval_secs": 30
+            },
+            "markets": [
+                { "address": "0x0000000000000000000000000000000000000001" },
+                { "address": "0x0000000000000000000000000000000000000002", "poll_interval_secs": 5 }
+            ]
+        }"#);
+        let first = market(&config, "0x0000000000000000000000000000000000000001");
+        assert_eq!(first.alert_cooldown_secs, Some(600));
+        assert_eq!(first.notification_enabled, Some(false));
+        assert_eq!(first.webhook_url.as_ref().unwrap().expose(), "https://defaults.example/notify");
+        assert_eq!(first.liquidity_threshold.as_ref().unwrap().default_value(), "200");
+        assert_eq!(first.poll_interval_secs, Some(30));
+        assert_eq!(market(&config, "0x0000000000000000000000000000000000000002").poll_interval_secs, Some(5));
+    }
+
+    #[test]
+    fn a_market_without_any_webhook_is_rejected() {
+        let path = std::env::temp_dir().join(format!("compound-monitor-{}-no-webhook.json", std::process::id()));
+        fs::write(&path, r#"{
+            "rpc_url": "http://127.0.0.1:1",
+            "poll_interval_secs": 60,
+            "liquidity_threshold": "100",
+            "markets": [{ "address": "0x0000000000000000000000000000000000000001" }]
+        }"#).unwrap();
+        let result = Config::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", result.unwrap_err()).contains("No webhook_url for market"));
+    }
+
+    /// Answer to a mocked JSON-RPC call: the result, or the message of an error
+    type RpcAnswer = std::result::Result<serde_json::Value, String>;
+
+    /// Serve JSON-RPC over HTTP on a local port, answering each call with
+    /// `answer(method, params)`. Returns the URL to use as rpc_url.
+    async fn mock_rpc(answer: fn(&str, &serde_json::Value) -> RpcAnswer) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let body = loop {
+                        let mut chunk = [0u8; 4096];
+                        let read = socket.read(&mut chunk).await.unwrap();
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&chunk[..read]);
+                        let text = String::from_utf8_lossy(&request).to_string();
+                        let Some((head, body)) = text.split_once("\r\n\r\n") else { continue };
+                        let length = head.lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    };
+
+                    let call: serde_json::Value = serde_json::from_str(&body).unwrap();
+                    let response = match answer(call["method"].as_str().unwrap(), &call["params"]) {
+                        Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }),
+                        Err(message) => serde_json::json!({ "jsonrpc": "2.0", "id": call["id"], "error": { "code": 3, "message": message } }),
+                    };
+                    let response = response.to_string();
+                    let _ = socket.write_all(format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        response.len(), response
+                    ).as_bytes()).await;
+                });
+            }
+        });
+        url
+    }
+
+    /// A chain where every contract call reverts except a cToken's comptroller()
+    fn comptroller_only(method: &str, params: &serde_json::Value) -> RpcAnswer {
+        match method {
+            "eth_chainId" => Ok(serde_json::json!("0x1")),
+            "eth_blockNumber" => Ok(serde_json::json!("0x10")),
+            "eth_call" => {
+                let selector = format!("0x{}", ethers::utils::hex::encode(ethers::utils::id("comptroller()")));
+                match params[0]["data"].as_str().or(params[0]["input"].as_str()) {
+                    Some(data) if data.starts_with(&selector) => {
+                        Ok(serde_json::json!(format!("0x{:0>64}", "3d9819210a31b4961b30ef54be2aed79b9c9cd3b")))
+                    }
+                    _ => Err("execution reverted".to_string()),
+                }
+            }
+            _ => Err(format!("unsupported method {}", method)),
+        }
+    }
+
+    #[tokio::test]
+    async fn usd_pricing_and_caps_are_probed_independently() {
+        let rpc_url = mock_rpc(comptroller_only).await;
+        let config = load_config("capabilities", &format!(r#"{{
+            "compound_version": "v2",
+            "rpc_url": "{}",
+            "rpc_max_retries": 0,
+            "market_address": "0x39AA39c021dfbaE8faC545936693aC917d5E7563",
+            "webhook_url": "https://hooks.example/notify",
+            "poll_interval_secs": 60,
+            "liquidity_threshold": "100",
+            "usd_pricing": true,
+            "price_feed_address": "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6",
+            "cap_headroom_pct": 10
+        }}"#, rpc_url));
+
+        let monitor = CompoundMonitor::new(config).await.unwrap();
+        monitor.detect_capabilities().await.unwrap();
+
+        let capabilities = monitor.capabilities();
+        assert!(!capabilities.usd_pricing);
+        assert!(!capabilities.supply_caps);
+        assert!(!capabilities.borrow_caps);
+    }
+
+    #[tokio::test]
+    async fn scanned_markets_ignore_the_configured_market_settings() {
+        let config = load_config("scan", r#"{
+            "compound_version": "v2",
+            "rpc_url": "http://127.0.0.1:1",
+            "market_address": "0x39AA39c021dfbaE8faC545936693aC917d5E7563",
+            "webhook_url": "https://hooks.example/notify",
+            "poll_interval_secs": 60,
+            "liquidity_threshold": "50000",
+            "threshold_unit": "usd",
+            "usd_pricing": true,
+            "price_feed_address": "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6",
+            "cap_headroom_pct": 10
+        }"#);
+        let monitor = CompoundMonitor::new(config).await.unwrap();
+
+        let scanned = monitor.scan_monitor(&ScanMarket {
+            address: "0xc3d688B66703497DAA19211EEdff47f25384cdc3".to_string(),
+            version: CompoundVersion::V3,
+        }).unwrap();
+        assert_eq!(scanned.config.market_address, "0xc3d688B66703497DAA19211EEdff47f25384cdc3");
+        assert_eq!(scanned.config.compound_version, CompoundVersion::V3);
+        assert_eq!(scanned.config.price_feed_address, None);
+        assert_eq!(scanned.config.cap_headroom_pct, None);
+        assert!(!scanned.usd_pricing_enabled());
+    }
+
+    /// A monitor of a dead RPC endpoint evaluating `alert_expression`
+    async fn expression_monitor(alert_expression: &str) -> Result<CompoundMonitor> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        alert_expression.hash(&mut hasher);
+        let config = load_config(&format!("expression-{:x}", hasher.finish()), &serde_json::json!({
+            "rpc_url": "http://127.0.0.1:1",
+            "market_address": "0xc3d688B66703497DAA19211EEdff47f25384cdc3",
+            "webhook_url": "https://hooks.example/notify",
+            "poll_interval_secs": 60,
+            "liquidity_threshold": "1000",
+            "alert_expression": alert_expression,
+        }).to_string());
+        CompoundMonitor::new(config).await
+    }
+
+    /// A V2-like snapshot: utilization known, no APYs
+    fn expression_snapshot() -> MarketSnapshot {
+        MarketSnapshot {
+            symbol: "cUSDC".to_string(),
+            available_liquidity: U256::from(500),
+            total_borrows: U256::from(9500),
+            reserves: I256::from(10),
+            total_supply: Some(U256::from(10000)),
+            utilization: Some(95.0),
+            supply_apy: None,
+            borrow_apy: None,
+            base_price_usd: None,
+            available_liquidity_usd: None,
+            supply_cap: None,
+            borrow_cap: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn alert_expression_compound_conditions() {
+        let threshold = U256::from(1000);
+        let monitor = expression_monitor("liquidity < threshold && utilization > 90").await.unwrap();
+        assert_eq!(
+            monitor.alert_condition(&expression_snapshot(), threshold).as_deref(),
+            Some("Alert expression matched: liquidity < threshold && utilization > 90")
+        );
+
+        let monitor = expression_monitor("liquidity < threshold && utilization > 99").await.unwrap();
+        assert_eq!(monitor.alert_condition(&expression_snapshot(), threshold), None);
+
+        let monitor = expression_monitor("reserves < 0 || borrows > total_supply").await.unwrap();
+        assert_eq!(monitor.alert_condition(&expression_snapshot(), threshold), None);
+    }
+
+    #[tokio::test]
+    async fn alert_expression_errors_never_fire() {
+        let threshold = U256::from(1000);
+        let snapshot = expression_snapshot();
+
+        // Unknown, and known but unavailable for this market
+        for expression in ["tvl < 1", "supply_apy < 1", "liquidity < threshold && borrow_apy > 5"] {
+            let monitor = expression_monitor(expression).await.unwrap();
+            assert!(monitor.evaluate_alert_expression(monitor.alert_expression.as_ref().unwrap(), &snapshot, threshold).is_err());
+            assert_eq!(monitor.alert_condition(&snapshot, threshold), None, "{}", expression);
+        }
+
+        // Evaluates, but not to a boolean
+        let monitor = expression_monitor("liquidity + 1").await.unwrap();
+        assert!(monitor.evaluate_alert_expression(monitor.alert_expression.as_ref().unwrap(), &snapshot, threshold).is_err());
+        assert_eq!(monitor.alert_condition(&snapshot, threshold), None);
+
+        // Incomplete, but accepted by the parser
+        let monitor = expression_monitor("liquidity <").await.unwrap();
+        assert_eq!(monitor.alert_condition(&snapshot, threshold), None);
+
+        // Doesn't parse: refused at startup
+        let e = expression_monitor("(liquidity < 1").await.err().unwrap();
+        assert!(format!("{:#}", e).contains("Invalid alert_expression"));
+    }
+
+    fn scheduled(days: &[&str], start: &str, end: &str) -> ScheduledThreshold {
+        let window = ThresholdWindow {
+            days: days.iter().map(|day| day.to_string()).collect(),
+            start: start.to_string(),
+            end: end.to_string(),
+            value: "1".to_string(),
+        };
+        ScheduledThreshold::parse(&window, None).unwrap()
+    }
+
+    /// 2024-01-05 is a Friday
+    fn utc(day: u32, hour: u32, minute: u32) -> chrono::DateTime<chrono::Utc> {
+        use chrono::TimeZone;
+        chrono::Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn scheduled_threshold_boundaries() {
+        let window = scheduled(&[], "09:00", "17:00");
+        assert!(!window.is_active(utc(5, 8, 59)));
+        assert!(window.is_active(utc(5, 9, 0)));
+        assert!(window.is_active(utc(5, 16, 59)));
+        assert!(!window.is_active(utc(5, 17, 0)));
+    }
+
+    #[test]
+    fn scheduled_threshold_wraps_past_midnight() {
+        let window = scheduled(&[], "22:00", "06:00");
+        assert!(!window.is_active(utc(5, 21, 59)));
+        assert!(window.is_active(utc(5, 22, 0)));
+        assert!(window.is_active(utc(5, 23, 59)));
+        assert!(window.is_active(utc(6, 0, 0)));
+        assert!(window.is_active(utc(6, 5, 59)));
+        assert!(!window.is_active(utc(6, 6, 0)));
+        assert!(!window.is_active(utc(6, 12, 0)));
+
+        // The hours after midnight belong to the day the window started
+        let friday_night = scheduled(&["fri"], "22:00", "06:00");
+        assert!(friday_night.is_active(utc(5, 22, 0)));
+        assert!(friday_night.is_active(utc(6, 1, 0)));
+        assert!(!friday_night.is_active(utc(5, 1, 0)));
+        assert!(!friday_night.is_active(utc(6, 22, 0)));
+    }
+
+    #[test]
+    fn parse_amount_scales_decimals() {
+        assert_eq!(parse_amount("1000000", None).unwrap(), U256::from(1_000_000));
+        assert_eq!(parse_amount("1000000", Some(6)).unwrap(), U256::from(1_000_000));
+        assert_eq!(parse_amount("1000.5", Some(6)).unwrap(), U256::from(1_000_500_000));
+        assert_eq!(parse_amount("1000.5", Some(18)).unwrap(), U256::from(1_000_500u64) * U256::exp10(15));
+        assert_eq!(parse_amount("0.000001", Some(6)).unwrap(), U256::one());
+        assert_eq!(parse_amount("1.", Some(0)).unwrap(), U256::one());
+
+        // Decimal thresholds need the token's decimals
+        assert!(parse_amount("1000.5", None).is_err());
+    }
+
+    #[test]
+    fn parse_amount_rejects_invalid_amounts() {
+        // More fractional digits than the token has
+        assert!(parse_amount("0.0000001", Some(6)).is_err());
+        assert!(parse_amount("1.5", Some(0)).is_err());
+        assert!(parse_amount("-1.5", Some(6)).is_err());
+
+        // "max" is only an amount for withdraw, never a threshold
+        assert!(parse_amount("max", None).is_err());
+        assert!(parse_amount("max", Some(18)).is_err());
+
+        // Overflow
+        let max = U256::MAX.to_string();
+        assert_eq!(parse_amount(&max, None).unwrap(), U256::MAX);
+        assert!(parse_amount(&format!("{}0", max), None).is_err());
+        assert!(parse_amount(&format!("{}.0", max), Some(18)).is_err());
+    }
+
+    #[tokio::test]
+    async fn rapid_drain_alerts_wait_for_warmup_reads() {
+        let config = load_config("warmup", &serde_json::json!({
+            "rpc_url": "http://127.0.0.1:1",
+            "market_address": "0xc3d688B66703497DAA19211EEdff47f25384cdc3",
+            "webhook_url": "https://hooks.example/notify",
+            "poll_interval_secs": 60,
+            "liquidity_threshold": "1",
+            "liquidity_drop_pct": 50.0,
+            "warmup_reads": 3,
+        }).to_string());
+        let monitor = CompoundMonitor::new(config).await.unwrap();
+        let read = |liquidity: u64| monitor.liquidity_drop(&MarketSnapshot {
+            available_liquidity: U256::from(liquidity),
+            ..expression_snapshot()
+        });
+
+        // Every read drains 90%, but the first three are warmup
+        assert!(read(100_000).is_none());
+        assert!(read(10_000).is_none());
+        assert!(read(1_000).is_none());
+        let drop = read(100).unwrap();
+        assert_eq!((drop.from.as_str(), drop.to.as_str()), ("1000", "100"));
+        assert_eq!(drop.drop_pct, 90.0);
+
+        // After warmup, only drops beyond liquidity_drop_pct fire
+        assert!(read(60).is_none());
+        assert!(read(20).is_some());
+    }
 }
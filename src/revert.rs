@@ -0,0 +1,56 @@
+use ethers::abi::{self, ParamType, Token};
+use ethers::utils::{hex, id};
+
+/// Custom errors raised by Comet, with a human-readable explanation
+const COMET_ERRORS: &[(&str, &str)] = &[
+    ("Paused()", "this market action is paused"),
+    ("NotCollateralized()", "the account would be undercollateralized"),
+    ("BorrowTooSmall()", "the borrow is below the market minimum"),
+    ("InsufficientReserves()", "the protocol has insufficient reserves"),
+    ("SupplyCapExceeded()", "the supply cap would be exceeded"),
+    ("BadAsset()", "the asset is not supported by this market"),
+    ("BadPrice()", "the price feed returned an invalid price"),
+    ("Unauthorized()", "the caller is not authorized"),
+    ("TransferInFailed()", "the token transfer into the market failed (check balance and allowance)"),
+    ("TransferOutFailed()", "the token transfer out of the market failed"),
+    ("NoSelfTransfer()", "cannot transfer to the same account"),
+    ("NotLiquidatable()", "the account is not liquidatable"),
+    ("NotForSale()", "the collateral is not for sale"),
+    ("TooMuchSlippage()", "too much slippage"),
+    ("TimestampTooLarge()", "the block timestamp is too large"),
+    ("Absurd()", "the market reached an invalid state"),
+];
+
+/// Turn raw revert data into a human-readable reason: `Error(string)` messages,
+/// `Panic(uint256)` codes and Comet's custom errors are recognized.
+pub fn decode(data: &[u8]) -> String {
+    if data.len() < 4 {
+        return "reverted without a reason".to_string();
+    }
+
+    let (selector, payload) = data.split_at(4);
+
+    if selector == id("Error(string)") {
+        if let Ok(tokens) = abi::decode(&[ParamType::String], payload) {
+            if let Some(Token::String(reason)) = tokens.into_iter().next() {
+                return reason;
+            }
+        }
+    }
+
+    if selector == id("Panic(uint256)") {
+        if let Ok(tokens) = abi::decode(&[ParamType::Uint(256)], payload) {
+            if let Some(Token::Uint(code)) = tokens.into_iter().next() {
+                return format!("panic (code 0x{:x})", code);
+            }
+        }
+    }
+
+    for (signature, description) in COMET_ERRORS {
+        if selector == id(signature) {
+            return format!("{} ({})", description, signature.trim_end_matches("()"));
+        }
+    }
+
+    format!("unrecognized revert data 0x{}", hex::encode(data))
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A sensitive string (private key, tokenized URL) that is redacted whenever it is
+/// formatted with `{}` or `{:?}` or serialized. Use [`Secret::expose`] to get the
+/// real value.
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Return the unredacted value. Never pass the result to a log macro.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl FromStr for Secret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Secret(s.to_string()))
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&mask(&self.0))
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&mask(&self.0))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", mask(&self.0))
+    }
+}
+
+/// Redact a secret for display.
+///
+/// URLs keep their scheme and host (API keys and webhook tokens live in the path or
+/// query), e.g. `https://hooks.slack.com/…`. Anything else keeps only a short prefix
+/// and suffix, e.g. `0x1234…abcd`.
+pub fn mask(value: &str) -> String {
+    if let Some((scheme, rest)) = value.split_once("://") {
+        // Drop any userinfo (user:password@) before the host
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+        let host = authority.rsplit('@').next().unwrap_or("");
+        if authority.len() == rest.len() && !authority.contains('@') {
+            return value.to_string();
+        }
+        return format!("{}://{}/…", scheme, host);
+    }
+
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 12 {
+        return "…".to_string();
+    }
+
+    let prefix: String = chars[..6].iter().collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", prefix, suffix)
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::debug;
+
+/// Largest request head read before giving up on a connection
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// A response from a route handler
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Self { status: 200, content_type, body }
+    }
+}
+
+/// Serve GET requests on `listener` forever, answering each path with
+/// `handler` (None is a 404). Just enough HTTP/1.1 for scrapers and probes.
+pub async fn serve<F>(listener: TcpListener, handler: F)
+where
+    F: Fn(&str) -> Option<Response> + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                debug!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let handler = Arc::clone(&handler);
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, handler.as_ref()).await {
+                debug!("Failed to answer {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle<F>(mut stream: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(&str) -> Option<Response>,
+{
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_BYTES {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    // Request line: METHOD PATH VERSION
+    let head = String::from_utf8_lossy(&request);
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    // Ignore any query string
+    let path = path.split('?').next().unwrap_or_default();
+
+    let response = match method {
+        "GET" | "HEAD" => handler(path).unwrap_or(Response {
+            status: 404,
+            content_type: "text/plain",
+            body: "not found\n".to_string(),
+        }),
+        _ => Response { status: 405, content_type: "text/plain", body: "method not allowed\n".to_string() },
+    };
+
+    let reason = match response.status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    let mut reply = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, reason, response.content_type, response.body.len()
+    );
+    if method != "HEAD" {
+        reply.push_str(&response.body);
+    }
+
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await
+}
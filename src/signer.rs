@@ -0,0 +1,139 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use ethers::signers::{HDPath, Ledger, LedgerError, LocalWallet, Signer, WalletError};
+use ethers::types::transaction::{eip2718::TypedTransaction, eip712::Eip712};
+use ethers::types::{Address, Signature};
+use std::fmt;
+use std::sync::Arc;
+use tracing::info;
+
+/// Where the signing key lives
+#[derive(Debug, Clone)]
+enum Key {
+    Local(LocalWallet),
+    /// Shared so one device connection serves every transaction of a command
+    Ledger(Arc<Ledger>),
+}
+
+/// Transaction signer backed by a private key or a Ledger hardware wallet
+#[derive(Debug, Clone)]
+pub struct TxSigner {
+    key: Key,
+    chain_id: u64,
+}
+
+/// Error from either kind of signer
+#[derive(Debug)]
+pub enum TxSignerError {
+    Local(WalletError),
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for TxSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxSignerError::Local(e) => e.fmt(f),
+            TxSignerError::Ledger(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TxSignerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TxSignerError::Local(e) => e.source(),
+            TxSignerError::Ledger(e) => e.source(),
+        }
+    }
+}
+
+impl From<WalletError> for TxSignerError {
+    fn from(e: WalletError) -> Self {
+        TxSignerError::Local(e)
+    }
+}
+
+impl From<LedgerError> for TxSignerError {
+    fn from(e: LedgerError) -> Self {
+        TxSignerError::Ledger(e)
+    }
+}
+
+impl TxSigner {
+    pub fn from_private_key(private_key: &str) -> anyhow::Result<Self> {
+        let wallet = private_key.parse::<LocalWallet>().context("Invalid private key")?;
+        let chain_id = wallet.chain_id();
+        Ok(Self { key: Key::Local(wallet), chain_id })
+    }
+
+    /// Connect to the first Ledger found over USB and read the account at `path`.
+    /// The Ethereum app must be open on the device.
+    pub async fn connect_ledger(path: HDPath) -> anyhow::Result<Self> {
+        let description = path.to_string();
+        // The HID backend panics rather than failing when USB is unavailable
+        let ledger = tokio::spawn(Ledger::new(path, 1))
+            .await
+            .map_err(|_| anyhow::anyhow!("Failed to open USB HID devices to look for a Ledger"))?
+            .map_err(|e| anyhow::anyhow!("Failed to connect to Ledger at {} ({}). Is it unlocked with the Ethereum app open?", description, e))?;
+        info!("Connected to Ledger (account {:?} at {})", ledger.address(), description);
+
+        Ok(Self { key: Key::Ledger(Arc::new(ledger)), chain_id: 1 })
+    }
+}
+
+#[async_trait]
+impl Signer for TxSigner {
+    type Error = TxSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(&self, message: S) -> Result<Signature, Self::Error> {
+        match self.key {
+            Key::Local(ref wallet) => Ok(wallet.sign_message(message).await?),
+            Key::Ledger(ref ledger) => {
+                info!("Confirm the message on your Ledger...");
+                Ok(ledger.sign_message(message).await?)
+            }
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        // The chain id is ours, whatever the key was created with
+        let mut tx = tx.clone();
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+
+        match self.key {
+            Key::Local(ref wallet) => Ok(wallet.sign_transaction(&tx).await?),
+            Key::Ledger(ref ledger) => {
+                info!("Review and confirm the transaction on your Ledger...");
+                Ok(ledger.sign_transaction(&tx).await?)
+            }
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(&self, payload: &T) -> Result<Signature, Self::Error> {
+        match self.key {
+            Key::Local(ref wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            Key::Ledger(ref ledger) => {
+                info!("Confirm the typed data on your Ledger...");
+                Ok(ledger.sign_typed_data(payload).await?)
+            }
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self.key {
+            Key::Local(ref wallet) => wallet.address(),
+            Key::Ledger(ref ledger) => ledger.address(),
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use ethers::types::{H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxOperation {
+    Supply,
+    Withdraw,
+    /// V3 withdraw of the base asset beyond the supplied balance
+    Borrow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxStatus {
+    /// Recorded before broadcast, or broadcast but not yet mined
+    Pending,
+    Confirmed,
+    Failed,
+}
+
+/// One entry in the transaction log. Every state change of a transaction is
+/// appended as a new line; the last line for a given operation wins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxRecord {
+    pub timestamp: i64,
+    pub operation: TxOperation,
+    pub market: String,
+    pub amount: String,
+    pub from: H160,
+    /// Nonce the transaction is expected to use, recorded before broadcast so a
+    /// crash between send and receipt can still be detected on restart
+    pub nonce: U256,
+    pub idempotency_key: Option<String>,
+    pub tx_hash: Option<H256>,
+    pub status: TxStatus,
+}
+
+impl TxRecord {
+    /// Append an updated copy of this record with a new status
+    pub fn with_status(&self, status: TxStatus) -> Self {
+        TxRecord {
+            timestamp: chrono::Utc::now().timestamp(),
+            status,
+            ..self.clone()
+        }
+    }
+}
+
+/// Append-only JSONL log of transactions sent by this tool
+pub struct TxLog {
+    path: PathBuf,
+}
+
+impl TxLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        TxLog { path: path.into() }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &TxRecord) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open transaction log {}", self.path.display()))?;
+
+        let line = serde_json::to_string(record)?;
+        writeln!(file, "{}", line)
+            .with_context(|| format!("Failed to write transaction log {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    /// All records in the order they were written. A missing log is empty.
+    pub fn records(&self) -> Result<Vec<TxRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read transaction log {}", self.path.display()))?;
+
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Corrupt entry in transaction log {}", self.path.display()))
+            })
+            .collect()
+    }
+
+    /// The most recent state of every transaction, in the order they were
+    /// first recorded. A transaction is identified by its sender and nonce.
+    pub fn latest_states(&self) -> Result<Vec<TxRecord>> {
+        let mut latest: Vec<TxRecord> = Vec::new();
+        for record in self.records()? {
+            match latest
+                .iter_mut()
+                .find(|seen| seen.from == record.from && seen.nonce == record.nonce)
+            {
+                Some(seen) => *seen = record,
+                None => latest.push(record),
+            }
+        }
+        Ok(latest)
+    }
+
+    /// The most recent state of the transaction recorded under `key`
+    pub fn latest_for_key(&self, key: &str) -> Result<Option<TxRecord>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .rev()
+            .find(|record| record.idempotency_key.as_deref() == Some(key)))
+    }
+}